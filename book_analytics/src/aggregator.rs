@@ -0,0 +1,609 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::isbn;
+use serde::{Deserialize, Serialize};
+
+use crate::window::{Granularity, WindowSpec};
+
+/// What a count is broken down by.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Dimension {
+    Total,
+    /// The ISBN registration group, such as `978-0`.
+    RegistrationGroup(String),
+    /// The ISBN publisher prefix, such as `978-0-306`.
+    Publisher(String),
+}
+
+impl Dimension {
+    /// The dimensions a book with `isbn` is counted in. Books with an unparseable
+    /// ISBN only count towards the total.
+    pub fn of(isbn: &str) -> Vec<Dimension> {
+        let mut dimensions = vec![Dimension::Total];
+        if let Some(parts) = isbn::parse(isbn) {
+            dimensions.push(Dimension::RegistrationGroup(parts.group_prefix()));
+            if let Some(publisher) = parts.publisher_prefix() {
+                dimensions.push(Dimension::Publisher(publisher));
+            }
+        }
+        dimensions
+    }
+}
+
+/// A created book at the time it was produced, in epoch milliseconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BookEvent {
+    pub partition: i32,
+    pub timestamp: i64,
+    pub id: i32,
+    pub title: String,
+    pub isbn: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecentCreation {
+    pub id: i32,
+    pub title: String,
+    pub isbn: String,
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PublisherCount {
+    pub publisher: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct WindowCount {
+    pub spec: WindowSpec,
+    pub start: i64,
+    pub end: i64,
+    pub dimension: Dimension,
+    pub count: u64,
+}
+
+fn window_count(
+    spec: &WindowSpec,
+    (start, dimension): &(i64, Dimension),
+    count: &u64,
+) -> WindowCount {
+    WindowCount {
+        spec: *spec,
+        start: *start,
+        end: spec.end(*start),
+        dimension: dimension.clone(),
+        count: *count,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AggregatorConfig {
+    pub specs: Vec<WindowSpec>,
+    /// How far behind the latest event time seen events may arrive; the watermark
+    /// trails the latest event time by this much.
+    pub max_out_of_orderness: Duration,
+    /// How long after the watermark passes its end a window still accepts events.
+    pub allowed_lateness: Duration,
+    /// How long closed windows are kept for queries, behind the watermark.
+    pub retention: Duration,
+    /// How long, in processing time, a partition may go without events before it
+    /// stops holding the watermark back.
+    pub idle_timeout: Duration,
+    /// How many of the latest creations are kept.
+    pub recent_capacity: usize,
+}
+
+impl Default for AggregatorConfig {
+    fn default() -> Self {
+        let quarter_hour = Duration::from_secs(15 * 60);
+        Self {
+            specs: vec![
+                WindowSpec::tumbling(Granularity::Minute),
+                WindowSpec::tumbling(Granularity::Hour),
+                WindowSpec::tumbling(Granularity::Day),
+                WindowSpec::hopping(Granularity::Hour, quarter_hour),
+                WindowSpec::hopping(Granularity::Day, Duration::from_secs(3600)),
+            ],
+            max_out_of_orderness: Duration::from_secs(30),
+            allowed_lateness: Duration::from_secs(60),
+            retention: Duration::from_secs(90 * 86_400),
+            idle_timeout: Duration::from_secs(5 * 60),
+            recent_capacity: 1000,
+        }
+    }
+}
+
+/// What processing an event did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Processed {
+    /// Whether the event was too late for every window it falls in and was dropped.
+    pub late: bool,
+    /// Windows the advancing watermark closed, with their final counts.
+    pub closed: Vec<WindowCount>,
+}
+
+/// Everything an `Aggregator` has computed, in a form that serializes to JSON.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AggregatorState {
+    pub partition_watermarks: HashMap<i32, i64>,
+    pub open_from: Vec<(WindowSpec, i64)>,
+    pub windows: Vec<WindowCount>,
+    pub late_events: u64,
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub recent: Vec<RecentCreation>,
+}
+
+/// Event-time window counts of created books. The watermark of each partition
+/// trails the latest event time read from it, and the overall watermark is the
+/// minimum across partitions, so a slow partition holds windows open. Partitions
+/// idle for longer than the idle timeout are left out, unless all of them are.
+pub struct Aggregator {
+    config: AggregatorConfig,
+    partition_watermarks: HashMap<i32, i64>,
+    /// When each partition last had an event, in epoch milliseconds of processing time.
+    /// Not checkpointed: after a restore the idle timeout starts over.
+    last_active: HashMap<i32, i64>,
+    /// The processing time of the latest event.
+    now: i64,
+    counts: HashMap<WindowSpec, BTreeMap<(i64, Dimension), u64>>,
+    /// The first window start of each spec that is still open.
+    open_from: HashMap<WindowSpec, i64>,
+    late_events: u64,
+    /// Every book processed, including those too late for any window.
+    total: u64,
+    /// The latest creations by event time, latest first.
+    recent: VecDeque<RecentCreation>,
+}
+
+impl Aggregator {
+    pub fn new(config: AggregatorConfig) -> Self {
+        let counts = config
+            .specs
+            .iter()
+            .map(|spec| (*spec, BTreeMap::new()))
+            .collect();
+        Self {
+            config,
+            partition_watermarks: HashMap::new(),
+            last_active: HashMap::new(),
+            now: i64::MIN,
+            counts,
+            open_from: HashMap::new(),
+            late_events: 0,
+            total: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Resumes from `state`, dropping windows of specs that are no longer configured.
+    pub fn restore(config: AggregatorConfig, state: AggregatorState) -> Self {
+        let mut aggregator = Self::new(config);
+        aggregator.partition_watermarks = state.partition_watermarks;
+        aggregator.late_events = state.late_events;
+        aggregator.total = state.total;
+        aggregator.recent = state.recent.into();
+        for (spec, open_from) in state.open_from {
+            if aggregator.counts.contains_key(&spec) {
+                aggregator.open_from.insert(spec, open_from);
+            }
+        }
+        for window in state.windows {
+            if let Some(counts) = aggregator.counts.get_mut(&window.spec) {
+                counts.insert((window.start, window.dimension), window.count);
+            }
+        }
+        aggregator
+    }
+
+    pub fn snapshot(&self) -> AggregatorState {
+        AggregatorState {
+            partition_watermarks: self.partition_watermarks.clone(),
+            open_from: self
+                .open_from
+                .iter()
+                .map(|(spec, open_from)| (*spec, *open_from))
+                .collect(),
+            windows: self
+                .counts
+                .iter()
+                .flat_map(|(spec, counts)| {
+                    counts
+                        .iter()
+                        .map(|(key, count)| window_count(spec, key, count))
+                })
+                .collect(),
+            late_events: self.late_events,
+            total: self.total,
+            recent: self.recent.iter().cloned().collect(),
+        }
+    }
+
+    pub fn specs(&self) -> &[WindowSpec] {
+        &self.config.specs
+    }
+
+    /// The overall watermark, once an event has been seen.
+    pub fn watermark(&self) -> Option<i64> {
+        let idle_before = self
+            .now
+            .saturating_sub(self.config.idle_timeout.as_millis() as i64);
+        self.partition_watermarks
+            .iter()
+            .filter(|(partition, _)| {
+                self.last_active
+                    .get(partition)
+                    .is_none_or(|last_active| *last_active >= idle_before)
+            })
+            .map(|(_, watermark)| *watermark)
+            .min()
+            .or_else(|| self.partition_watermarks.values().min().copied())
+    }
+
+    pub fn late_events(&self) -> u64 {
+        self.late_events
+    }
+
+    pub fn process(&mut self, event: &BookEvent) -> Processed {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        self.process_at(event, now)
+    }
+
+    /// Processes `event` as read at `now`, in epoch milliseconds of processing time.
+    pub fn process_at(&mut self, event: &BookEvent, now: i64) -> Processed {
+        self.now = self.now.max(now);
+        // Restored partitions start their idle timeout with the first event read.
+        for partition in self.partition_watermarks.keys() {
+            self.last_active.entry(*partition).or_insert(self.now);
+        }
+        self.last_active.insert(event.partition, self.now);
+        let counted = self.count(event);
+        let watermark = event.timestamp - self.config.max_out_of_orderness.as_millis() as i64;
+        let partition_watermark = self
+            .partition_watermarks
+            .entry(event.partition)
+            .or_insert(watermark);
+        *partition_watermark = (*partition_watermark).max(watermark);
+
+        Processed {
+            late: !counted,
+            closed: self.advance(),
+        }
+    }
+
+    /// Counts a book read from the database rather than a topic. It leaves the
+    /// watermarks alone, so old books don't close the windows live events fall in.
+    pub fn backfill(&mut self, event: &BookEvent) -> bool {
+        self.count(event)
+    }
+
+    /// Counts a book read from the database without a creation time, which belongs
+    /// to no window and only counts towards the total.
+    pub fn backfill_undated(&mut self) {
+        self.total += 1;
+    }
+
+    /// Counts `event` in every open window it falls in, returning whether there was one.
+    fn count(&mut self, event: &BookEvent) -> bool {
+        let dimensions = Dimension::of(&event.isbn);
+        let mut counted = false;
+        for (spec, counts) in self.counts.iter_mut() {
+            let open_from = self.open_from.get(spec).copied().unwrap_or(i64::MIN);
+            for start in spec.windows(event.timestamp) {
+                if start < open_from {
+                    break;
+                }
+                counted = true;
+                for dimension in &dimensions {
+                    *counts.entry((start, dimension.clone())).or_default() += 1;
+                }
+            }
+        }
+        if !counted {
+            self.late_events += 1;
+        }
+        self.total += 1;
+        self.remember(event);
+        counted
+    }
+
+    fn remember(&mut self, event: &BookEvent) {
+        let position = self
+            .recent
+            .iter()
+            .position(|recent| recent.timestamp <= event.timestamp)
+            .unwrap_or(self.recent.len());
+        if position >= self.config.recent_capacity {
+            return;
+        }
+        self.recent.insert(
+            position,
+            RecentCreation {
+                id: event.id,
+                title: event.title.clone(),
+                isbn: event.isbn.clone(),
+                timestamp: event.timestamp,
+            },
+        );
+        self.recent.truncate(self.config.recent_capacity);
+    }
+
+    /// Closes the windows the watermark has passed by more than the allowed lateness,
+    /// and evicts those older than the retention.
+    fn advance(&mut self) -> Vec<WindowCount> {
+        let Some(watermark) = self.watermark() else {
+            return Vec::new();
+        };
+        let closed_before = watermark - self.config.allowed_lateness.as_millis() as i64;
+        let evict_before = watermark - self.config.retention.as_millis() as i64;
+        let mut closed = Vec::new();
+        for (spec, counts) in self.counts.iter_mut() {
+            // A partition read for the first time can lower the watermark, but
+            // closed windows stay closed.
+            let previous = self.open_from.get(spec).copied();
+            let open_from = spec
+                .first_ending_after(closed_before)
+                .max(previous.unwrap_or(i64::MIN));
+            self.open_from.insert(*spec, open_from);
+            if previous.is_none_or(|previous| previous < open_from) {
+                let from = previous.unwrap_or(i64::MIN);
+                closed.extend(
+                    counts
+                        .range((from, Dimension::Total)..(open_from, Dimension::Total))
+                        .map(|(key, count)| window_count(spec, key, count)),
+                );
+            }
+            *counts = counts.split_off(&(spec.first_ending_after(evict_before), Dimension::Total));
+        }
+        closed
+    }
+
+    /// Counts of `spec` in `dimension` for windows starting in `[from, to)`, open or closed.
+    pub fn counts(
+        &self,
+        spec: &WindowSpec,
+        dimension: &Dimension,
+        from: i64,
+        to: i64,
+    ) -> Vec<WindowCount> {
+        let Some(counts) = self.counts.get(spec).filter(|_| from < to) else {
+            return Vec::new();
+        };
+        counts
+            .range((from, Dimension::Total)..(to, Dimension::Total))
+            .filter(|((_, d), _)| d == dimension)
+            .map(|(key, count)| window_count(spec, key, count))
+            .collect()
+    }
+
+    /// Like `counts`, with a zero count for every window in range nothing was counted in.
+    /// `None` when `spec` is not configured.
+    pub fn series(
+        &self,
+        spec: &WindowSpec,
+        dimension: &Dimension,
+        from: i64,
+        to: i64,
+    ) -> Option<Vec<WindowCount>> {
+        if !self.counts.contains_key(spec) {
+            return None;
+        }
+        let counts: HashMap<i64, u64> = self
+            .counts(spec, dimension, from, to)
+            .into_iter()
+            .map(|window| (window.start, window.count))
+            .collect();
+        let first = from.div_euclid(spec.hop_ms) * spec.hop_ms;
+        let first = if first < from {
+            first + spec.hop_ms
+        } else {
+            first
+        };
+        Some(
+            (0..)
+                .map(|i| first + i * spec.hop_ms)
+                .take_while(|start| *start < to)
+                .map(|start| WindowCount {
+                    spec: *spec,
+                    start,
+                    end: spec.end(start),
+                    dimension: dimension.clone(),
+                    count: counts.get(&start).copied().unwrap_or_default(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Publisher prefixes by the books counted in windows of `spec` starting in
+    /// `[from, to)`, most first. Use a tumbling spec, or books are counted repeatedly.
+    pub fn top_publishers(
+        &self,
+        spec: &WindowSpec,
+        from: i64,
+        to: i64,
+        limit: usize,
+    ) -> Vec<PublisherCount> {
+        let mut totals: HashMap<&str, u64> = HashMap::new();
+        if let Some(counts) = self.counts.get(spec).filter(|_| from < to) {
+            for ((_, dimension), count) in
+                counts.range((from, Dimension::Total)..(to, Dimension::Total))
+            {
+                if let Dimension::Publisher(publisher) = dimension {
+                    *totals.entry(publisher).or_default() += count;
+                }
+            }
+        }
+        let mut top: Vec<PublisherCount> = totals
+            .into_iter()
+            .map(|(publisher, count)| PublisherCount {
+                publisher: publisher.to_owned(),
+                count,
+            })
+            .collect();
+        top.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.publisher.cmp(&b.publisher))
+        });
+        top.truncate(limit);
+        top
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The latest `limit` creations by event time, latest first.
+    pub fn recent(&self, limit: usize) -> Vec<RecentCreation> {
+        self.recent.iter().take(limit).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aggregator() -> Aggregator {
+        Aggregator::new(AggregatorConfig {
+            specs: vec![WindowSpec::tumbling(Granularity::Minute)],
+            max_out_of_orderness: Duration::from_secs(10),
+            allowed_lateness: Duration::from_secs(0),
+            retention: Duration::from_secs(3600),
+            idle_timeout: Duration::from_secs(60),
+            recent_capacity: 2,
+        })
+    }
+
+    fn event(timestamp: i64) -> BookEvent {
+        BookEvent {
+            partition: 0,
+            timestamp,
+            id: timestamp as i32,
+            title: "Title".to_owned(),
+            isbn: "978-0-306-40615-7".to_owned(),
+        }
+    }
+
+    #[test]
+    fn counts_by_group_and_publisher() {
+        let mut aggregator = aggregator();
+        aggregator.process(&event(1_000));
+        aggregator.process(&event(2_000));
+        let spec = WindowSpec::tumbling(Granularity::Minute);
+        let publisher = Dimension::Publisher("978-0-306".to_owned());
+        assert_eq!(2, aggregator.counts(&spec, &publisher, 0, 60_000)[0].count);
+        let group = Dimension::RegistrationGroup("978-0".to_owned());
+        assert_eq!(2, aggregator.counts(&spec, &group, 0, 60_000)[0].count);
+    }
+
+    #[test]
+    fn closes_windows_behind_the_watermark_and_drops_late_events() {
+        let mut aggregator = aggregator();
+        assert!(aggregator.process(&event(1_000)).closed.is_empty());
+        assert!(!aggregator.process(&event(65_000)).late);
+
+        let processed = aggregator.process(&event(71_000));
+        let totals: Vec<_> = processed
+            .closed
+            .iter()
+            .filter(|c| c.dimension == Dimension::Total)
+            .collect();
+        assert_eq!(1, totals.len());
+        assert_eq!((0, 1), (totals[0].start, totals[0].count));
+
+        assert!(aggregator.process(&event(30_000)).late);
+        assert_eq!(1, aggregator.late_events());
+    }
+
+    #[test]
+    fn answers_series_top_publishers_and_recent() {
+        let mut aggregator = aggregator();
+        aggregator.process(&event(1_000));
+        aggregator.process(&event(125_000));
+        aggregator.process(&BookEvent {
+            isbn: "978-1-4028-9462-6".to_owned(),
+            ..event(70_000)
+        });
+        let spec = WindowSpec::tumbling(Granularity::Minute);
+
+        let series = aggregator
+            .series(&spec, &Dimension::Total, 0, 180_000)
+            .unwrap();
+        let counts: Vec<_> = series.iter().map(|w| (w.start, w.count)).collect();
+        assert_eq!(vec![(0, 1), (60_000, 1), (120_000, 1)], counts);
+
+        let top = aggregator.top_publishers(&spec, 0, 180_000, 1);
+        assert_eq!(
+            vec![PublisherCount {
+                publisher: "978-0-306".to_owned(),
+                count: 2
+            }],
+            top
+        );
+
+        assert_eq!(3, aggregator.total());
+        let recent: Vec<_> = aggregator.recent(5).iter().map(|r| r.timestamp).collect();
+        assert_eq!(vec![125_000, 70_000], recent);
+    }
+
+    #[test]
+    fn restores_from_a_snapshot() {
+        let mut aggregator = aggregator();
+        aggregator.process(&event(1_000));
+        aggregator.process(&event(75_000));
+        let state = aggregator.snapshot();
+
+        let mut restored = Aggregator::restore(aggregator.config.clone(), state.clone());
+        assert_eq!(state, restored.snapshot());
+        assert!(restored.process(&event(2_000)).late);
+    }
+
+    #[test]
+    fn watermark_is_the_minimum_across_partitions() {
+        let mut aggregator = aggregator();
+        aggregator.process(&event(1_000));
+        aggregator.process(&BookEvent {
+            partition: 1,
+            ..event(200_000)
+        });
+        assert_eq!(Some(-9_000), aggregator.watermark());
+        assert!(!aggregator.process(&event(2_000)).late);
+    }
+
+    #[test]
+    fn idle_partitions_stop_holding_the_watermark_back() {
+        let mut aggregator = aggregator();
+        let idle_timeout = aggregator.config.idle_timeout.as_millis() as i64;
+        aggregator.process_at(&event(1_000), 0);
+        let partition_1 = |timestamp| BookEvent {
+            partition: 1,
+            ..event(timestamp)
+        };
+        assert!(aggregator
+            .process_at(&partition_1(200_000), 1)
+            .closed
+            .is_empty());
+        assert_eq!(Some(-9_000), aggregator.watermark());
+
+        let processed = aggregator.process_at(&partition_1(201_000), idle_timeout + 1);
+        assert_eq!(Some(191_000), aggregator.watermark());
+        assert!(processed
+            .closed
+            .iter()
+            .any(|c| (c.start, c.dimension.clone()) == (0, Dimension::Total)));
+
+        // Once it has events again, the partition counts, though closed windows stay closed.
+        aggregator.process_at(&event(150_000), idle_timeout + 2);
+        assert_eq!(Some(140_000), aggregator.watermark());
+        assert!(aggregator.process_at(&event(3_000), idle_timeout + 3).late);
+    }
+}
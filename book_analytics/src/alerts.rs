@@ -0,0 +1,477 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use chrono::{DateTime, Datelike, Timelike, Utc, Weekday};
+use common::events::{
+    dto::{AlertStatus, AnalyticsAlert},
+    topics,
+};
+use futures::future::BoxFuture;
+use kafka::producer::KafkaProducer;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{error, info, warn};
+
+use crate::{
+    aggregator::{Aggregator, Dimension},
+    window::{Granularity, WindowSpec},
+};
+
+#[derive(Error, Debug)]
+pub enum AlertError {
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Serialization error")]
+    SerializationError(#[from] serde_json::Error),
+
+    #[error("Webhook error")]
+    WebhookError(#[from] reqwest::Error),
+
+    #[error("Kafka error: {0}")]
+    KafkaError(String),
+}
+
+/// How a rule decides it is violated.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Detector {
+    /// The count over the latest `windows` windows is above `threshold`.
+    Above { windows: usize, threshold: u64 },
+    /// The count over the latest `windows` windows is below `threshold`.
+    Below { windows: usize, threshold: u64 },
+    /// The latest window's count is more than `threshold` standard deviations away
+    /// from the mean of the `history` windows before it.
+    ZScore { history: usize, threshold: f64 },
+}
+
+/// UTC hours a rule is evaluated in, by the start of its latest window: from
+/// `from_hour` inclusive to `to_hour` exclusive.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ActiveHours {
+    pub from_hour: u32,
+    pub to_hour: u32,
+    #[serde(default)]
+    pub weekdays_only: bool,
+}
+
+impl ActiveHours {
+    pub fn contains(&self, timestamp: i64) -> bool {
+        let Some(time) = DateTime::<Utc>::from_timestamp_millis(timestamp) else {
+            return false;
+        };
+        let weekend = matches!(time.weekday(), Weekday::Sat | Weekday::Sun);
+        (!self.weekdays_only || !weekend)
+            && time.hour() >= self.from_hour
+            && time.hour() < self.to_hour
+    }
+}
+
+/// A rule over the windows of `spec` in `dimension`. Rules summing several windows
+/// should use a tumbling spec, or books are counted more than once.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    pub name: String,
+    #[serde(default = "default_severity")]
+    pub severity: String,
+    pub spec: WindowSpec,
+    #[serde(default = "default_dimension")]
+    pub dimension: Dimension,
+    pub detector: Detector,
+    #[serde(default)]
+    pub active_hours: Option<ActiveHours>,
+}
+
+fn default_severity() -> String {
+    "warning".to_owned()
+}
+
+fn default_dimension() -> Dimension {
+    Dimension::Total
+}
+
+/// What a rule found over the windows from `window_start` to `window_end`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    pub value: f64,
+    pub threshold: f64,
+    pub violated: bool,
+    pub window_start: i64,
+    pub window_end: i64,
+}
+
+impl AlertRule {
+    /// Evaluates the rule over the latest windows ending by `until`. `None` outside
+    /// its active hours, or when the aggregator does not keep windows of its spec.
+    pub fn evaluate(&self, aggregator: &Aggregator, until: i64) -> Option<Evaluation> {
+        let last =
+            (until - self.spec.size.millis()).div_euclid(self.spec.hop_ms) * self.spec.hop_ms;
+        if let Some(hours) = &self.active_hours {
+            if !hours.contains(last) {
+                return None;
+            }
+        }
+        let series = |windows: usize| {
+            let from = last - windows.saturating_sub(1) as i64 * self.spec.hop_ms;
+            aggregator
+                .series(&self.spec, &self.dimension, from, last + 1)
+                .map(|series| series.into_iter().map(|w| w.count).collect::<Vec<_>>())
+        };
+        let (value, threshold, violated, windows) = match self.detector {
+            Detector::Above { windows, threshold } => {
+                let count: u64 = series(windows)?.iter().sum();
+                (count as f64, threshold as f64, count > threshold, windows)
+            }
+            Detector::Below { windows, threshold } => {
+                let count: u64 = series(windows)?.iter().sum();
+                (count as f64, threshold as f64, count < threshold, windows)
+            }
+            Detector::ZScore { history, threshold } => {
+                let counts = series(history + 1)?;
+                let (latest, history_counts) = counts.split_last()?;
+                let z = z_score(*latest as f64, history_counts);
+                (z, threshold, z.abs() >= threshold, history + 1)
+            }
+        };
+        Some(Evaluation {
+            value,
+            threshold,
+            violated,
+            window_start: last - windows.saturating_sub(1) as i64 * self.spec.hop_ms,
+            window_end: self.spec.end(last),
+        })
+    }
+
+    fn message(&self, evaluation: &Evaluation, status: AlertStatus) -> String {
+        let state = match status {
+            AlertStatus::Firing => "firing",
+            AlertStatus::Resolved => "resolved",
+        };
+        let comparison = match self.detector {
+            Detector::Above { .. } => "books, limit above",
+            Detector::Below { .. } => "books, limit below",
+            Detector::ZScore { .. } => "standard deviations from the mean, limit",
+        };
+        format!(
+            "{} {}: {} {} {} for {:?} in {} windows",
+            self.name,
+            state,
+            evaluation.value,
+            comparison,
+            evaluation.threshold,
+            self.dimension,
+            self.spec.size
+        )
+    }
+}
+
+/// How many standard deviations `value` is from the mean of `history`. The deviation
+/// is floored at one book, so a flat history doesn't make every change an anomaly.
+fn z_score(value: f64, history: &[u64]) -> f64 {
+    if history.is_empty() {
+        return 0.0;
+    }
+    let n = history.len() as f64;
+    let mean = history.iter().map(|count| *count as f64).sum::<f64>() / n;
+    let variance = history
+        .iter()
+        .map(|count| (*count as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    (value - mean) / variance.sqrt().max(1.0)
+}
+
+/// More than 500 books in 5 minutes, no book in an hour during business hours, and
+/// an hourly count far off the last day's.
+pub fn default_rules() -> Vec<AlertRule> {
+    vec![
+        AlertRule {
+            name: "creation_burst".to_owned(),
+            severity: "warning".to_owned(),
+            spec: WindowSpec::tumbling(Granularity::Minute),
+            dimension: Dimension::Total,
+            detector: Detector::Above {
+                windows: 5,
+                threshold: 500,
+            },
+            active_hours: None,
+        },
+        AlertRule {
+            name: "no_creations".to_owned(),
+            severity: "critical".to_owned(),
+            spec: WindowSpec::tumbling(Granularity::Hour),
+            dimension: Dimension::Total,
+            detector: Detector::Below {
+                windows: 1,
+                threshold: 1,
+            },
+            active_hours: Some(ActiveHours {
+                from_hour: 9,
+                to_hour: 17,
+                weekdays_only: true,
+            }),
+        },
+        AlertRule {
+            name: "hourly_anomaly".to_owned(),
+            severity: "info".to_owned(),
+            spec: WindowSpec::tumbling(Granularity::Hour),
+            dimension: Dimension::Total,
+            detector: Detector::ZScore {
+                history: 24,
+                threshold: 3.0,
+            },
+            active_hours: None,
+        },
+    ]
+}
+
+/// Rules from a JSON array of `AlertRule`s.
+pub fn load_rules(path: &Path) -> Result<Vec<AlertRule>, AlertError> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Evaluates rules, raising an alert when one starts being violated and a resolve
+/// when it stops. A rule that keeps being violated is not raised again.
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    firing: HashMap<String, AnalyticsAlert>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>, aggregator: &Aggregator) -> Self {
+        for rule in &rules {
+            if !aggregator.specs().contains(&rule.spec) {
+                warn!(
+                    "Rule {} uses {:?} windows, which are not kept",
+                    rule.name, rule.spec
+                );
+            }
+        }
+        Self {
+            rules,
+            firing: HashMap::new(),
+        }
+    }
+
+    /// Alerts raised and not resolved yet.
+    pub fn firing(&self) -> Vec<AnalyticsAlert> {
+        self.firing.values().cloned().collect()
+    }
+
+    /// Evaluates every rule over windows ending by `until`, returning the alerts that
+    /// started or stopped firing. A rule outside its active hours resolves.
+    pub fn evaluate(
+        &mut self,
+        aggregator: &Aggregator,
+        until: i64,
+        now: i64,
+    ) -> Vec<AnalyticsAlert> {
+        let mut changes = Vec::new();
+        for rule in &self.rules {
+            let evaluation = rule.evaluate(aggregator, until);
+            let violated = evaluation.as_ref().is_some_and(|e| e.violated);
+            let status = match (violated, self.firing.contains_key(&rule.name)) {
+                (true, false) => AlertStatus::Firing,
+                (false, true) => AlertStatus::Resolved,
+                _ => continue,
+            };
+            let alert = match evaluation {
+                Some(evaluation) => AnalyticsAlert {
+                    rule: rule.name.clone(),
+                    status,
+                    severity: rule.severity.clone(),
+                    message: rule.message(&evaluation, status),
+                    value: evaluation.value,
+                    threshold: evaluation.threshold,
+                    window_start: evaluation.window_start,
+                    window_end: evaluation.window_end,
+                    raised_at: now,
+                },
+                None => AnalyticsAlert {
+                    status,
+                    message: format!("{} resolved: outside its active hours", rule.name),
+                    raised_at: now,
+                    ..self.firing[&rule.name].clone()
+                },
+            };
+            match status {
+                AlertStatus::Firing => self.firing.insert(rule.name.clone(), alert.clone()),
+                AlertStatus::Resolved => self.firing.remove(&rule.name),
+            };
+            changes.push(alert);
+        }
+        changes
+    }
+}
+
+/// Where alerts are delivered.
+pub trait AlertSink: Send + Sync {
+    fn send<'a>(&'a self, alert: &'a AnalyticsAlert) -> BoxFuture<'a, Result<(), AlertError>>;
+}
+
+pub struct LogSink;
+
+impl AlertSink for LogSink {
+    fn send<'a>(&'a self, alert: &'a AnalyticsAlert) -> BoxFuture<'a, Result<(), AlertError>> {
+        Box::pin(async move {
+            match alert.status {
+                AlertStatus::Firing => warn!("[{}] {}", alert.severity, alert.message),
+                AlertStatus::Resolved => info!("[{}] {}", alert.severity, alert.message),
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Posts each alert as JSON.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+impl AlertSink for WebhookSink {
+    fn send<'a>(&'a self, alert: &'a AnalyticsAlert) -> BoxFuture<'a, Result<(), AlertError>> {
+        Box::pin(async move {
+            self.client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(alert)?)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+/// Publishes each alert to the `AnalyticsAlert` topic, keyed by rule.
+pub struct KafkaSink {
+    producer: KafkaProducer,
+}
+
+impl KafkaSink {
+    pub fn new(producer: KafkaProducer) -> Self {
+        Self { producer }
+    }
+}
+
+impl AlertSink for KafkaSink {
+    fn send<'a>(&'a self, alert: &'a AnalyticsAlert) -> BoxFuture<'a, Result<(), AlertError>> {
+        Box::pin(async move {
+            if self
+                .producer
+                .publish::<topics::AnalyticsAlert>(alert.rule.clone(), alert.clone())
+                .await
+            {
+                Ok(())
+            } else {
+                Err(AlertError::KafkaError(format!(
+                    "alert {} was not published",
+                    alert.rule
+                )))
+            }
+        })
+    }
+}
+
+/// Sends every alert to every sink. A failing sink doesn't keep the others from
+/// receiving it.
+pub async fn dispatch(sinks: &[Box<dyn AlertSink>], alerts: &[AnalyticsAlert]) {
+    for alert in alerts {
+        for sink in sinks {
+            if let Err(e) = sink.send(alert).await {
+                error!("Error sending alert {}: {}", alert.rule, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{AggregatorConfig, BookEvent};
+
+    const MINUTE: i64 = 60_000;
+
+    fn aggregator(timestamps: &[i64]) -> Aggregator {
+        let mut aggregator = Aggregator::new(AggregatorConfig {
+            specs: vec![WindowSpec::tumbling(Granularity::Minute)],
+            ..AggregatorConfig::default()
+        });
+        for (id, timestamp) in timestamps.iter().enumerate() {
+            aggregator.process(&BookEvent {
+                partition: 0,
+                timestamp: *timestamp,
+                id: id as i32,
+                title: "Title".to_owned(),
+                isbn: "978-0-306-40615-7".to_owned(),
+            });
+        }
+        aggregator
+    }
+
+    fn burst_rule() -> AlertRule {
+        AlertRule {
+            name: "burst".to_owned(),
+            severity: default_severity(),
+            spec: WindowSpec::tumbling(Granularity::Minute),
+            dimension: Dimension::Total,
+            detector: Detector::Above {
+                windows: 2,
+                threshold: 2,
+            },
+            active_hours: None,
+        }
+    }
+
+    #[test]
+    fn fires_once_and_resolves() {
+        let aggregator = aggregator(&[MINUTE, MINUTE + 1, 2 * MINUTE, 2 * MINUTE + 1]);
+        let mut engine = AlertEngine::new(vec![burst_rule()], &aggregator);
+
+        let fired = engine.evaluate(&aggregator, 3 * MINUTE, 0);
+        assert_eq!(1, fired.len());
+        assert_eq!(AlertStatus::Firing, fired[0].status);
+        assert_eq!(4.0, fired[0].value);
+        assert_eq!(
+            (MINUTE, 3 * MINUTE),
+            (fired[0].window_start, fired[0].window_end)
+        );
+        assert!(engine.evaluate(&aggregator, 3 * MINUTE, 1).is_empty());
+
+        let resolved = engine.evaluate(&aggregator, 4 * MINUTE, 2);
+        assert_eq!(1, resolved.len());
+        assert_eq!(AlertStatus::Resolved, resolved[0].status);
+        assert!(engine.firing().is_empty());
+    }
+
+    #[test]
+    fn z_score_flags_spikes_against_history() {
+        assert_eq!(0.0, z_score(3.0, &[3, 3, 3]));
+        assert_eq!(7.0, z_score(10.0, &[3, 3, 3]));
+        assert!(z_score(4.0, &[2, 4, 2, 4]).abs() < 3.0);
+    }
+
+    #[test]
+    fn active_hours_skip_weekends() {
+        let hours = ActiveHours {
+            from_hour: 9,
+            to_hour: 17,
+            weekdays_only: true,
+        };
+        // 2024-06-03 was a Monday.
+        let monday = 1_717_372_800_000;
+        assert!(hours.contains(monday + 9 * 3_600_000));
+        assert!(!hours.contains(monday + 17 * 3_600_000));
+        assert!(!hours.contains(monday - 86_400_000 + 10 * 3_600_000));
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aggregator::{Aggregator, AggregatorConfig, AggregatorState},
+    backfill::Backfilled,
+    state::{StateError, StateStore},
+};
+
+const CHECKPOINT_KEY: &str = "checkpoint";
+
+/// Aggregation state together with the next offset to read of each partition it covers.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub offsets: HashMap<i32, i64>,
+    pub state: AggregatorState,
+    /// The highest book id counted by a backfill, whose events are skipped.
+    #[serde(default)]
+    pub backfill_boundary: Option<i32>,
+    /// Ids up to the boundary the backfill didn't have, whose events are counted.
+    #[serde(default)]
+    pub backfill_missing: Vec<(i32, i32)>,
+    /// Sink files prepared with this checkpoint, to move into place once it is saved.
+    #[serde(default)]
+    pub pending_files: Vec<String>,
+}
+
+/// Saves a checkpoint every `interval` and decides which records it already covers.
+///
+/// The checkpoint is written before its offsets are committed to Kafka. After a crash
+/// between the two, records up to the checkpoint are redelivered and skipped by
+/// `is_covered`, so every record is counted exactly once.
+pub struct Checkpointer<S: StateStore> {
+    store: S,
+    interval: Duration,
+    offsets: HashMap<i32, i64>,
+    backfilled: Option<Backfilled>,
+    pending_files: Vec<String>,
+    last_saved: Instant,
+    dirty: bool,
+}
+
+impl<S: StateStore> Checkpointer<S> {
+    pub fn new(store: S, interval: Duration) -> Self {
+        Self {
+            store,
+            interval,
+            offsets: HashMap::new(),
+            backfilled: None,
+            pending_files: Vec::new(),
+            last_saved: Instant::now(),
+            dirty: false,
+        }
+    }
+
+    /// The aggregator as of the last checkpoint, or a fresh one if there is none.
+    pub fn restore(&mut self, config: AggregatorConfig) -> Result<Aggregator, StateError> {
+        match self.store.get(CHECKPOINT_KEY)? {
+            Some(bytes) => {
+                let checkpoint: Checkpoint = serde_json::from_slice(&bytes)?;
+                self.offsets = checkpoint.offsets;
+                self.backfilled = checkpoint.backfill_boundary.map(|boundary| Backfilled {
+                    boundary,
+                    missing: checkpoint.backfill_missing,
+                });
+                self.pending_files = checkpoint.pending_files;
+                Ok(Aggregator::restore(config, checkpoint.state))
+            }
+            None => Ok(Aggregator::new(config)),
+        }
+    }
+
+    /// The next offset to read of each partition, as of the last record processed.
+    pub fn offsets(&self) -> &HashMap<i32, i64> {
+        &self.offsets
+    }
+
+    /// Sink files the last checkpoint was saved with.
+    pub fn pending_files(&self) -> &[String] {
+        &self.pending_files
+    }
+
+    /// Records the sink files to save with the next checkpoint.
+    pub fn set_pending_files(&mut self, files: Vec<String>) {
+        self.pending_files = files;
+    }
+
+    /// Whether the record at `offset` is already part of the state.
+    pub fn is_covered(&self, partition: i32, offset: i64) -> bool {
+        self.offsets
+            .get(&partition)
+            .is_some_and(|next| offset < *next)
+    }
+
+    /// Whether the book was counted by a backfill, so its event must not be.
+    pub fn is_backfilled(&self, book_id: i32) -> bool {
+        self.backfilled
+            .as_ref()
+            .is_some_and(|backfilled| backfilled.contains(book_id))
+    }
+
+    /// Starts over from `backfilled`, forgetting the offsets read so far.
+    pub fn reset_to_backfill(&mut self, backfilled: Option<Backfilled>) {
+        self.offsets.clear();
+        self.backfilled = backfilled;
+        self.dirty = true;
+    }
+
+    pub fn record(&mut self, partition: i32, offset: i64) {
+        self.offsets.insert(partition, offset + 1);
+        self.dirty = true;
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.dirty && self.last_saved.elapsed() >= self.interval
+    }
+
+    /// Saves `aggregator` with the offsets recorded so far, returning the offsets
+    /// to commit to Kafka now that they are durable.
+    pub fn save(&mut self, aggregator: &Aggregator) -> Result<HashMap<i32, i64>, StateError> {
+        let checkpoint = Checkpoint {
+            offsets: self.offsets.clone(),
+            state: aggregator.snapshot(),
+            backfill_boundary: self.backfilled.as_ref().map(|b| b.boundary),
+            backfill_missing: self
+                .backfilled
+                .as_ref()
+                .map(|b| b.missing.clone())
+                .unwrap_or_default(),
+            pending_files: self.pending_files.clone(),
+        };
+        self.store
+            .put(CHECKPOINT_KEY, serde_json::to_vec(&checkpoint)?)?;
+        self.last_saved = Instant::now();
+        self.dirty = false;
+        Ok(checkpoint.offsets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggregator::BookEvent,
+        state::InMemoryStore,
+        window::{Granularity, WindowSpec},
+    };
+
+    fn config() -> AggregatorConfig {
+        AggregatorConfig {
+            specs: vec![WindowSpec::tumbling(Granularity::Minute)],
+            ..AggregatorConfig::default()
+        }
+    }
+
+    #[test]
+    fn restores_state_and_skips_covered_records() {
+        let mut checkpointer = Checkpointer::new(InMemoryStore::default(), Duration::ZERO);
+        let mut aggregator = checkpointer.restore(config()).unwrap();
+        aggregator.process(&BookEvent {
+            partition: 0,
+            timestamp: 1_000,
+            id: 1,
+            title: "Title".to_owned(),
+            isbn: "978-0-306-40615-7".to_owned(),
+        });
+        checkpointer.record(0, 41);
+        assert!(checkpointer.is_due());
+        assert_eq!(
+            HashMap::from([(0, 42)]),
+            checkpointer.save(&aggregator).unwrap()
+        );
+        assert!(!checkpointer.is_due());
+
+        let mut restarted = Checkpointer::new(checkpointer.store, Duration::ZERO);
+        let restored = restarted.restore(config()).unwrap();
+        assert_eq!(aggregator.snapshot(), restored.snapshot());
+        assert!(restarted.is_covered(0, 41));
+        assert!(!restarted.is_covered(0, 42));
+        assert!(!restarted.is_covered(1, 0));
+
+        restarted.reset_to_backfill(Some(Backfilled {
+            boundary: 7,
+            missing: vec![(3, 3)],
+        }));
+        assert!(restarted.offsets().is_empty());
+        restarted.save(&restored).unwrap();
+
+        let mut backfilled = Checkpointer::new(restarted.store, Duration::ZERO);
+        backfilled.restore(config()).unwrap();
+        assert!(backfilled.is_backfilled(7));
+        assert!(!backfilled.is_backfilled(3));
+        assert!(!backfilled.is_backfilled(8));
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    extract::Query,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use axum_tracing_opentelemetry::opentelemetry_tracing_layer;
+use book_analytics::{
+    aggregator::{Aggregator, Dimension},
+    window::{Granularity, WindowSpec},
+};
+use kafka::lag::LagMonitor;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const MAX_WINDOWS: i64 = 10_000;
+const MAX_LIMIT: usize = 1000;
+
+pub type SharedAggregator = Arc<RwLock<Aggregator>>;
+
+pub async fn start_http_server(aggregator: SharedAggregator, lag_monitor: LagMonitor) {
+    let analytics_router = Router::new()
+        .route("/total", get(total))
+        .route("/counts", get(counts))
+        .route("/publishers/top", get(top_publishers))
+        .route("/recent", get(recent));
+    let api_router = Router::new().nest("/analytics", analytics_router);
+    let admin_router = Router::new().route("/lag", get(lag));
+    let app = Router::new()
+        .nest("/api", api_router)
+        .nest("/admin", admin_router)
+        .route("/metrics", get(metrics))
+        .layer(opentelemetry_tracing_layer())
+        .layer(Extension(aggregator))
+        .layer(Extension(lag_monitor));
+    let addr = SocketAddr::from(([127, 0, 0, 1], 8091));
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+        .unwrap()
+}
+
+/// Whether the client prefers `text/csv` to JSON, which is the default.
+fn wants_csv(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+    else {
+        return false;
+    };
+    let csv = preference(accept, "text", "csv");
+    csv.0 > 0.0 && csv > preference(accept, "application", "json")
+}
+
+/// The quality `accept` gives `type_/subtype`, from the most specific media range
+/// matching it, with that range's specificity to break ties between media types.
+fn preference(accept: &str, type_: &str, subtype: &str) -> (f32, u8) {
+    let mut best: Option<(u8, f32)> = None;
+    for range in accept.split(',') {
+        let mut params = range.split(';').map(str::trim);
+        let Some((range_type, range_subtype)) = params.next().and_then(|m| m.split_once('/'))
+        else {
+            continue;
+        };
+        let specificity = match (range_type, range_subtype) {
+            (t, s) if t.eq_ignore_ascii_case(type_) && s.eq_ignore_ascii_case(subtype) => 2,
+            (t, "*") if t.eq_ignore_ascii_case(type_) => 1,
+            ("*", "*") => 0,
+            _ => continue,
+        };
+        let quality = params
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .map_or(1.0, |(_, q)| q.trim().parse().unwrap_or(0.0));
+        if best.is_none_or(|(best, _)| specificity > best) {
+            best = Some((specificity, quality));
+        }
+    }
+    best.map_or((0.0, 0), |(specificity, quality)| (quality, specificity))
+}
+
+/// A response body and the rows it is written as in CSV.
+trait CsvRows: Serialize {
+    type Row: Serialize;
+
+    fn rows(&self) -> Vec<&Self::Row>;
+}
+
+impl<T: Serialize> CsvRows for Vec<T> {
+    type Row = T;
+
+    fn rows(&self) -> Vec<&T> {
+        self.iter().collect()
+    }
+}
+
+/// `body` as JSON, or its rows as CSV when the client accepts `text/csv`.
+fn negotiate<B: CsvRows>(headers: &HeaderMap, body: B) -> Response {
+    if !wants_csv(headers) {
+        return Json(body).into_response();
+    }
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in body.rows() {
+        if let Err(e) = writer.serialize(row) {
+            return bad_request(e.to_string());
+        }
+    }
+    match writer.into_inner() {
+        Ok(body) => ([(header::CONTENT_TYPE, "text/csv")], body).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+fn bad_request(error: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response()
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TotalResponse {
+    total: u64,
+}
+
+impl CsvRows for TotalResponse {
+    type Row = Self;
+
+    fn rows(&self) -> Vec<&Self> {
+        vec![self]
+    }
+}
+
+async fn total(headers: HeaderMap, Extension(aggregator): Extension<SharedAggregator>) -> Response {
+    let total = aggregator.read().unwrap().total();
+    negotiate(&headers, TotalResponse { total })
+}
+
+#[derive(Deserialize, Debug)]
+struct CountsQuery {
+    granularity: Granularity,
+    /// Epoch milliseconds, inclusive.
+    from: i64,
+    /// Epoch milliseconds, exclusive.
+    to: i64,
+    publisher: Option<String>,
+    group: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CountRow {
+    start: i64,
+    end: i64,
+    count: u64,
+}
+
+async fn counts(
+    headers: HeaderMap,
+    Query(query): Query<CountsQuery>,
+    Extension(aggregator): Extension<SharedAggregator>,
+) -> Response {
+    let spec = WindowSpec::tumbling(query.granularity);
+    if query.from >= query.to || query.to.saturating_sub(query.from) / spec.hop_ms > MAX_WINDOWS {
+        return bad_request(format!(
+            "from must be before to, spanning at most {} windows",
+            MAX_WINDOWS
+        ));
+    }
+    let dimension = match (query.publisher, query.group) {
+        (Some(_), Some(_)) => {
+            return bad_request("Give either publisher or group, not both".to_owned())
+        }
+        (Some(publisher), None) => Dimension::Publisher(publisher),
+        (None, Some(group)) => Dimension::RegistrationGroup(group),
+        (None, None) => Dimension::Total,
+    };
+    let series = aggregator
+        .read()
+        .unwrap()
+        .series(&spec, &dimension, query.from, query.to);
+    match series {
+        Some(series) => negotiate(
+            &headers,
+            series
+                .into_iter()
+                .map(|window| CountRow {
+                    start: window.start,
+                    end: window.end,
+                    count: window.count,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        None => bad_request(format!("{} windows are not kept", query.granularity)),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TopPublishersQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+    limit: Option<usize>,
+}
+
+async fn top_publishers(
+    headers: HeaderMap,
+    Query(query): Query<TopPublishersQuery>,
+    Extension(aggregator): Extension<SharedAggregator>,
+) -> Response {
+    let top = aggregator.read().unwrap().top_publishers(
+        &WindowSpec::tumbling(Granularity::Day),
+        query.from.unwrap_or(i64::MIN),
+        query.to.unwrap_or(i64::MAX),
+        query.limit.unwrap_or(10).min(MAX_LIMIT),
+    );
+    negotiate(&headers, top)
+}
+
+#[derive(Deserialize, Debug)]
+struct RecentQuery {
+    limit: Option<usize>,
+}
+
+async fn recent(
+    headers: HeaderMap,
+    Query(query): Query<RecentQuery>,
+    Extension(aggregator): Extension<SharedAggregator>,
+) -> Response {
+    let recent = aggregator
+        .read()
+        .unwrap()
+        .recent(query.limit.unwrap_or(10).min(MAX_LIMIT));
+    negotiate(&headers, recent)
+}
+
+async fn lag(Extension(lag_monitor): Extension<LagMonitor>) -> impl IntoResponse {
+    Json(json!({
+        "group": lag_monitor.group_id(),
+        "total_lag": lag_monitor.total_lag(),
+        "partitions": lag_monitor.snapshot(),
+    }))
+}
+
+/// Lag in the Prometheus text format, for scrapers without an OpenTelemetry collector.
+async fn metrics(Extension(lag_monitor): Extension<LagMonitor>) -> impl IntoResponse {
+    let mut body = String::from(
+        "# HELP kafka_consumer_lag Messages between the committed offset and the high watermark\n\
+         # TYPE kafka_consumer_lag gauge\n",
+    );
+    for lag in lag_monitor.snapshot() {
+        let _ = writeln!(
+            body,
+            "kafka_consumer_lag{{group=\"{}\",topic=\"{}\",partition=\"{}\"}} {}",
+            lag_monitor.group_id(),
+            lag.topic,
+            lag.partition,
+            lag.lag
+        );
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn accepting(accept: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static(accept));
+        headers
+    }
+
+    #[test]
+    fn test_wants_csv() {
+        assert!(!wants_csv(&HeaderMap::new()));
+        assert!(wants_csv(&accepting("text/csv")));
+        assert!(wants_csv(&accepting("text/csv, */*;q=0.8")));
+        assert!(wants_csv(&accepting("application/json;q=0.5, text/*")));
+        assert!(!wants_csv(&accepting("text/csv;q=0")));
+        assert!(!wants_csv(&accepting("text/csv;q=0, */*")));
+        assert!(!wants_csv(&accepting("text/csv;q=0.5, application/json")));
+        assert!(!wants_csv(&accepting("application/json, text/csv")));
+        assert!(!wants_csv(&accepting("*/*")));
+    }
+
+    #[tokio::test]
+    async fn test_counts_rejects_publisher_and_group() {
+        let aggregator = Arc::new(RwLock::new(Aggregator::new(Default::default())));
+        let query = CountsQuery {
+            granularity: Granularity::Minute,
+            from: 0,
+            to: 60_000,
+            publisher: Some("978-0-306".to_owned()),
+            group: Some("978-0".to_owned()),
+        };
+        let response = counts(HeaderMap::new(), Query(query), Extension(aggregator)).await;
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
@@ -1,14 +1,7 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub mod aggregator;
+pub mod alerts;
+pub mod backfill;
+pub mod checkpoint;
+pub mod parquet_sink;
+pub mod state;
+pub mod window;
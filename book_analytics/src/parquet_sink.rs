@@ -0,0 +1,655 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use apache_avro::{schema::RecordField, types::Value, AvroSchema, Schema};
+use arrow::{
+    array::{
+        Array, ArrayRef, BinaryBuilder, BooleanBuilder, Float32Builder, Float64Builder,
+        Int32Builder, Int64Builder, ListBuilder, StringBuilder,
+    },
+    datatypes::{DataType, Field, Schema as ArrowSchema, SchemaRef},
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+use chrono::{DateTime, Utc};
+use kafka::consumer::RecordMetadata;
+use parquet::{
+    arrow::ArrowWriter, basic::Compression, errors::ParquetError,
+    file::properties::WriterProperties,
+};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::info;
+
+use crate::aggregator::{Dimension, WindowCount};
+
+const TMP_SUFFIX: &str = ".tmp";
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Arrow error")]
+    ArrowError(#[from] ArrowError),
+
+    #[error("Parquet error")]
+    ParquetError(#[from] ParquetError),
+
+    #[error("Avro error")]
+    AvroError(#[from] apache_avro::Error),
+
+    #[error("Unsupported Avro schema: {0}")]
+    UnsupportedSchema(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct SinkOptions {
+    pub root: PathBuf,
+    /// A file is rolled once its rows are estimated to take this many bytes.
+    pub max_file_bytes: usize,
+    /// A file is rolled once its first row has been buffered this long.
+    pub max_file_age: Duration,
+}
+
+impl Default for SinkOptions {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("book_analytics_parquet"),
+            max_file_bytes: 64 * 1024 * 1024,
+            max_file_age: Duration::from_secs(300),
+        }
+    }
+}
+
+/// The Arrow schema of records of `schema`. Nullable unions become nullable columns,
+/// and arrays of ints, longs or strings become list columns.
+pub fn arrow_schema(schema: &Schema) -> Result<Vec<Field>, SinkError> {
+    let Schema::Record { fields, .. } = schema else {
+        return Err(SinkError::UnsupportedSchema(
+            "only records map to rows".to_owned(),
+        ));
+    };
+    fields.iter().map(arrow_field).collect()
+}
+
+fn arrow_field(field: &RecordField) -> Result<Field, SinkError> {
+    let (schema, nullable) = match &field.schema {
+        Schema::Union(union) if union.is_nullable() && union.variants().len() == 2 => {
+            let schema = union
+                .variants()
+                .iter()
+                .find(|variant| **variant != Schema::Null)
+                .expect("nullable unions have a non-null variant");
+            (schema, true)
+        }
+        schema => (schema, false),
+    };
+    let data_type = match schema {
+        Schema::Boolean => DataType::Boolean,
+        Schema::Int | Schema::Date => DataType::Int32,
+        Schema::Long | Schema::TimestampMillis | Schema::TimestampMicros => DataType::Int64,
+        Schema::Float => DataType::Float32,
+        Schema::Double => DataType::Float64,
+        Schema::Bytes => DataType::Binary,
+        Schema::String | Schema::Enum { .. } | Schema::Uuid => DataType::Utf8,
+        Schema::Array(items) if matches!(**items, Schema::Int | Schema::Long | Schema::String) => {
+            let item = match **items {
+                Schema::Int => DataType::Int32,
+                Schema::Long => DataType::Int64,
+                _ => DataType::Utf8,
+            };
+            // The list type of the builder, so the schema matches the arrays it builds.
+            ColumnBuilder::new(&DataType::List(Field::new("item", item, true).into()))
+                .finish()
+                .data_type()
+                .clone()
+        }
+        other => {
+            return Err(SinkError::UnsupportedSchema(format!(
+                "field {} has type {:?}",
+                field.name, other
+            )))
+        }
+    };
+    Ok(Field::new(&field.name, data_type, nullable))
+}
+
+enum ColumnBuilder {
+    Boolean(BooleanBuilder),
+    Int(Int32Builder),
+    Long(Int64Builder),
+    Float(Float32Builder),
+    Double(Float64Builder),
+    Bytes(BinaryBuilder),
+    String(StringBuilder),
+    IntList(ListBuilder<Int32Builder>),
+    LongList(ListBuilder<Int64Builder>),
+    StringList(ListBuilder<StringBuilder>),
+}
+
+impl ColumnBuilder {
+    fn new(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Boolean => Self::Boolean(BooleanBuilder::new()),
+            DataType::Int32 => Self::Int(Int32Builder::new()),
+            DataType::Int64 => Self::Long(Int64Builder::new()),
+            DataType::Float32 => Self::Float(Float32Builder::new()),
+            DataType::Float64 => Self::Double(Float64Builder::new()),
+            DataType::Binary => Self::Bytes(BinaryBuilder::new()),
+            DataType::List(item) => match item.data_type() {
+                DataType::Int32 => Self::IntList(ListBuilder::new(Int32Builder::new())),
+                DataType::Int64 => Self::LongList(ListBuilder::new(Int64Builder::new())),
+                _ => Self::StringList(ListBuilder::new(StringBuilder::new())),
+            },
+            _ => Self::String(StringBuilder::new()),
+        }
+    }
+
+    fn append(&mut self, value: Option<&Value>) {
+        let value = match value {
+            Some(Value::Union(_, value)) => Some(value.as_ref()),
+            value => value,
+        };
+        match (self, value) {
+            (Self::Boolean(b), Some(Value::Boolean(v))) => b.append_value(*v),
+            (Self::Int(b), Some(Value::Int(v) | Value::Date(v))) => b.append_value(*v),
+            (
+                Self::Long(b),
+                Some(Value::Long(v) | Value::TimestampMillis(v) | Value::TimestampMicros(v)),
+            ) => b.append_value(*v),
+            (Self::Float(b), Some(Value::Float(v))) => b.append_value(*v),
+            (Self::Double(b), Some(Value::Double(v))) => b.append_value(*v),
+            (Self::Bytes(b), Some(Value::Bytes(v))) => b.append_value(v),
+            (Self::String(b), Some(Value::String(v) | Value::Enum(_, v))) => b.append_value(v),
+            (Self::String(b), Some(Value::Uuid(v))) => b.append_value(v.to_string()),
+            (Self::IntList(b), Some(Value::Array(items))) => {
+                for item in items {
+                    match item {
+                        Value::Int(v) => b.values().append_value(*v),
+                        _ => b.values().append_null(),
+                    }
+                }
+                b.append(true);
+            }
+            (Self::LongList(b), Some(Value::Array(items))) => {
+                for item in items {
+                    match item {
+                        Value::Long(v) => b.values().append_value(*v),
+                        _ => b.values().append_null(),
+                    }
+                }
+                b.append(true);
+            }
+            (Self::StringList(b), Some(Value::Array(items))) => {
+                for item in items {
+                    match item {
+                        Value::String(v) => b.values().append_value(v),
+                        _ => b.values().append_null(),
+                    }
+                }
+                b.append(true);
+            }
+            (Self::Boolean(b), _) => b.append_null(),
+            (Self::Int(b), _) => b.append_null(),
+            (Self::Long(b), _) => b.append_null(),
+            (Self::Float(b), _) => b.append_null(),
+            (Self::Double(b), _) => b.append_null(),
+            (Self::Bytes(b), _) => b.append_null(),
+            (Self::String(b), _) => b.append_null(),
+            (Self::IntList(b), _) => b.append(false),
+            (Self::LongList(b), _) => b.append(false),
+            (Self::StringList(b), _) => b.append(false),
+        }
+    }
+
+    fn finish(self) -> ArrayRef {
+        match self {
+            Self::Boolean(mut b) => Arc::new(b.finish()),
+            Self::Int(mut b) => Arc::new(b.finish()),
+            Self::Long(mut b) => Arc::new(b.finish()),
+            Self::Float(mut b) => Arc::new(b.finish()),
+            Self::Double(mut b) => Arc::new(b.finish()),
+            Self::Bytes(mut b) => Arc::new(b.finish()),
+            Self::String(mut b) => Arc::new(b.finish()),
+            Self::IntList(mut b) => Arc::new(b.finish()),
+            Self::LongList(mut b) => Arc::new(b.finish()),
+            Self::StringList(mut b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+/// Rough in-memory size of a value, for rolling files by size.
+fn estimated_bytes(value: &Value) -> usize {
+    match value {
+        Value::String(s) | Value::Enum(_, s) => s.len(),
+        Value::Bytes(b) => b.len(),
+        Value::Union(_, value) => estimated_bytes(value),
+        Value::Record(fields) => fields.iter().map(|(_, v)| estimated_bytes(v)).sum(),
+        Value::Array(items) => items.iter().map(estimated_bytes).sum(),
+        _ => 8,
+    }
+}
+
+/// Rows of one file: the record fields of each row, by name.
+struct Part {
+    rows: Vec<HashMap<String, Value>>,
+    bytes: usize,
+    opened: Instant,
+}
+
+impl Part {
+    fn new() -> Self {
+        Self {
+            rows: Vec::new(),
+            bytes: 0,
+            opened: Instant::now(),
+        }
+    }
+
+    fn push(&mut self, fields: Vec<(String, Value)>) {
+        self.bytes += fields
+            .iter()
+            .map(|(_, v)| estimated_bytes(v))
+            .sum::<usize>();
+        self.rows.push(fields.into_iter().collect());
+    }
+
+    fn is_full(&self, options: &SinkOptions) -> bool {
+        self.bytes >= options.max_file_bytes || self.opened.elapsed() >= options.max_file_age
+    }
+
+    fn write(&self, schema: &SchemaRef, path: &Path) -> Result<(), SinkError> {
+        let mut builders: Vec<ColumnBuilder> = schema
+            .fields()
+            .iter()
+            .map(|field| ColumnBuilder::new(field.data_type()))
+            .collect();
+        for row in &self.rows {
+            for (field, builder) in schema.fields().iter().zip(builders.iter_mut()) {
+                builder.append(row.get(field.name()));
+            }
+        }
+        let columns = builders.into_iter().map(ColumnBuilder::finish).collect();
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(File::create(path)?, schema.clone(), Some(properties))?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(TMP_SUFFIX);
+    PathBuf::from(tmp)
+}
+
+/// The Hive-style partition directory of a day, such as `date=2024-06-01`.
+fn date_partition(timestamp: i64) -> String {
+    let date = DateTime::<Utc>::from_timestamp_millis(timestamp)
+        .map(|datetime| datetime.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "unknown".to_owned());
+    format!("date={}", date)
+}
+
+fn files(dir: &Path) -> Result<Vec<PathBuf>, SinkError> {
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+    for partition in fs::read_dir(dir)? {
+        let partition = partition?.path();
+        if partition.is_dir() {
+            for file in fs::read_dir(&partition)? {
+                files.push(file?.path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Removes temporary files left by a crash mid-write.
+fn remove_tmp_files(dir: &Path) -> Result<(), SinkError> {
+    for file in files(dir)? {
+        if file.to_string_lossy().ends_with(TMP_SUFFIX) {
+            info!("Removing uncommitted {}", file.display());
+            fs::remove_file(file)?;
+        }
+    }
+    Ok(())
+}
+
+/// `(partition, last offset)` of a file named `part-p<partition>-<first>-<last>.parquet`.
+fn parse_event_file(path: &Path) -> Option<(i32, i64)> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name
+        .strip_prefix("part-p")?
+        .strip_suffix(".parquet")?
+        .split('-');
+    let partition = parts.next()?.parse().ok()?;
+    let _first: i64 = parts.next()?.parse().ok()?;
+    let last = parts.next()?.parse().ok()?;
+    Some((partition, last))
+}
+
+/// An event file being filled, for one partition and day.
+struct OpenPart {
+    date: String,
+    first: i64,
+    last: i64,
+    part: Part,
+}
+
+/// Writes decoded events to `<root>/events/date=<day>/part-p<partition>-<first>-<last>.parquet`,
+/// one open file per partition, with the record's partition, offset and
+/// timestamp as extra columns.
+///
+/// A file is named by the offsets it holds and only appears once complete, so the
+/// committed files say which offsets are written: after a crash, reading again from
+/// before `committed_offsets` rewrites the missing files and skips the others.
+pub struct EventSink<T> {
+    options: SinkOptions,
+    dir: PathBuf,
+    schema: SchemaRef,
+    committed: HashMap<i32, i64>,
+    parts: HashMap<i32, OpenPart>,
+    payload: PhantomData<T>,
+}
+
+impl<T: AvroSchema + Serialize> EventSink<T> {
+    pub fn new(options: SinkOptions) -> Result<Self, SinkError> {
+        let dir = options.root.join("events");
+        let mut fields = arrow_schema(&T::get_schema())?;
+        fields.push(Field::new("_partition", DataType::Int32, false));
+        fields.push(Field::new("_offset", DataType::Int64, false));
+        fields.push(Field::new("_timestamp", DataType::Int64, true));
+
+        remove_tmp_files(&dir)?;
+        let mut committed = HashMap::new();
+        for file in files(&dir)? {
+            if let Some((partition, last)) = parse_event_file(&file) {
+                let next = committed.entry(partition).or_insert(last + 1);
+                *next = (*next).max(last + 1);
+            }
+        }
+        Ok(Self {
+            options,
+            dir,
+            schema: Arc::new(ArrowSchema::new(fields)),
+            committed,
+            parts: HashMap::new(),
+            payload: PhantomData,
+        })
+    }
+
+    /// The next offset to write of each partition with committed files.
+    pub fn committed_offsets(&self) -> &HashMap<i32, i64> {
+        &self.committed
+    }
+
+    pub fn write(&mut self, metadata: &RecordMetadata, payload: &T) -> Result<(), SinkError> {
+        if self
+            .committed
+            .get(&metadata.partition)
+            .is_some_and(|next| metadata.offset < *next)
+        {
+            return Ok(());
+        }
+        let Value::Record(mut fields) = apache_avro::to_value(payload)? else {
+            return Err(SinkError::UnsupportedSchema(
+                "payloads must be records".to_owned(),
+            ));
+        };
+        fields.push(("_partition".to_owned(), Value::Int(metadata.partition)));
+        fields.push(("_offset".to_owned(), Value::Long(metadata.offset)));
+        if let Some(timestamp) = metadata.timestamp {
+            fields.push(("_timestamp".to_owned(), Value::Long(timestamp)));
+        }
+
+        // Files of a partition hold consecutive offsets, so a new day rolls the
+        // previous file before one is opened for it.
+        let date = date_partition(metadata.timestamp.unwrap_or_else(now_millis));
+        if self
+            .parts
+            .get(&metadata.partition)
+            .is_some_and(|open| open.date != date)
+        {
+            self.roll(metadata.partition)?;
+        }
+        let open = self
+            .parts
+            .entry(metadata.partition)
+            .or_insert_with(|| OpenPart {
+                date,
+                first: metadata.offset,
+                last: metadata.offset,
+                part: Part::new(),
+            });
+        open.last = metadata.offset;
+        open.part.push(fields);
+        if open.part.is_full(&self.options) {
+            self.roll(metadata.partition)?;
+        }
+        Ok(())
+    }
+
+    /// Rolls every file that has reached its maximum age.
+    pub fn roll_expired(&mut self) -> Result<(), SinkError> {
+        let expired: Vec<i32> = self
+            .parts
+            .iter()
+            .filter(|(_, open)| open.part.is_full(&self.options))
+            .map(|(partition, _)| *partition)
+            .collect();
+        for partition in expired {
+            self.roll(partition)?;
+        }
+        Ok(())
+    }
+
+    fn roll(&mut self, partition: i32) -> Result<(), SinkError> {
+        let Some(open) = self.parts.remove(&partition) else {
+            return Ok(());
+        };
+        let path = self.dir.join(&open.date).join(format!(
+            "part-p{}-{:020}-{:020}.parquet",
+            partition, open.first, open.last
+        ));
+        // Written under a temporary name so `path` only ever holds a complete file.
+        let tmp = tmp_path(&path);
+        open.part.write(&self.schema, &tmp)?;
+        fs::rename(&tmp, &path)?;
+        self.committed.insert(partition, open.last + 1);
+        info!(
+            "Committed {} rows to {}",
+            open.part.rows.len(),
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+/// A closed window as a flat row.
+#[derive(Serialize, AvroSchema)]
+struct AggregateRow {
+    granularity: String,
+    hop_ms: i64,
+    window_start: i64,
+    window_end: i64,
+    dimension: String,
+    dimension_value: Option<String>,
+    count: i64,
+}
+
+impl From<&WindowCount> for AggregateRow {
+    fn from(window: &WindowCount) -> Self {
+        let (dimension, dimension_value) = match &window.dimension {
+            Dimension::Total => ("total", None),
+            Dimension::RegistrationGroup(group) => ("registration_group", Some(group.clone())),
+            Dimension::Publisher(publisher) => ("publisher", Some(publisher.clone())),
+        };
+        Self {
+            granularity: window.spec.size.to_string(),
+            hop_ms: window.spec.hop_ms,
+            window_start: window.start,
+            window_end: window.end,
+            dimension: dimension.to_owned(),
+            dimension_value,
+            count: window.count as i64,
+        }
+    }
+}
+
+/// Writes closed windows to `<root>/aggregates/date=<window start day>/`, committed
+/// together with the analytics checkpoint in two phases: `prepare` writes temporary
+/// files whose names go into the checkpoint, and `commit` renames them once it is saved.
+/// On startup, `recover` completes the renames the last checkpoint recorded and
+/// discards any other temporary file, whose windows the checkpoint does not count
+/// as closed and which will close again.
+pub struct AggregateSink {
+    dir: PathBuf,
+    schema: SchemaRef,
+    pending: Vec<WindowCount>,
+}
+
+impl AggregateSink {
+    pub fn new(options: &SinkOptions) -> Result<Self, SinkError> {
+        Ok(Self {
+            dir: options.root.join("aggregates"),
+            schema: Arc::new(ArrowSchema::new(arrow_schema(&AggregateRow::get_schema())?)),
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn stage(&mut self, windows: Vec<WindowCount>) {
+        self.pending.extend(windows);
+    }
+
+    /// Writes the staged windows to temporary files, returning the final paths to
+    /// record in the checkpoint.
+    pub fn prepare(&mut self) -> Result<Vec<String>, SinkError> {
+        let mut by_date: HashMap<String, Part> = HashMap::new();
+        for window in self.pending.drain(..) {
+            let Value::Record(fields) = apache_avro::to_value(AggregateRow::from(&window))? else {
+                unreachable!("AggregateRow is a record");
+            };
+            by_date
+                .entry(date_partition(window.start))
+                .or_insert_with(Part::new)
+                .push(fields);
+        }
+        let mut prepared = Vec::new();
+        for (date, part) in by_date {
+            let path = self
+                .dir
+                .join(date)
+                .join(format!("part-{}.parquet", now_nanos()));
+            part.write(&self.schema, &tmp_path(&path))?;
+            prepared.push(path.to_string_lossy().into_owned());
+        }
+        Ok(prepared)
+    }
+
+    /// Renames the temporary files of `prepared` paths into place.
+    pub fn commit(&self, prepared: &[String]) -> Result<(), SinkError> {
+        for path in prepared {
+            let tmp = tmp_path(Path::new(path));
+            if tmp.exists() {
+                fs::rename(&tmp, path)?;
+                info!("Committed {}", path);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn recover(&self, prepared: &[String]) -> Result<(), SinkError> {
+        self.commit(prepared)?;
+        remove_tmp_files(&self.dir)
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as i64
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_nanos()
+}
+
+#[cfg(test)]
+mod tests {
+    use common::events::dto::CreatedBook;
+
+    use super::*;
+
+    #[test]
+    fn maps_nullable_unions_to_nullable_columns() {
+        let fields = arrow_schema(&AggregateRow::get_schema()).unwrap();
+        let value = fields
+            .iter()
+            .find(|f| f.name() == "dimension_value")
+            .unwrap();
+        assert_eq!(
+            (&DataType::Utf8, true),
+            (value.data_type(), value.is_nullable())
+        );
+        let count = fields.iter().find(|f| f.name() == "count").unwrap();
+        assert_eq!(
+            (&DataType::Int64, false),
+            (count.data_type(), count.is_nullable())
+        );
+    }
+
+    #[test]
+    fn maps_int_arrays_to_list_columns() {
+        let fields = arrow_schema(&CreatedBook::get_schema()).unwrap();
+        let authors = fields.iter().find(|f| f.name() == "author_ids").unwrap();
+        let DataType::List(item) = authors.data_type() else {
+            panic!("author_ids is not a list");
+        };
+        assert_eq!(&DataType::Int32, item.data_type());
+
+        let mut builder = ColumnBuilder::new(authors.data_type());
+        builder.append(Some(&Value::Array(vec![Value::Int(1), Value::Int(2)])));
+        builder.append(None);
+        let array = builder.finish();
+        assert_eq!(authors.data_type(), array.data_type());
+        assert_eq!(1, array.null_count());
+    }
+
+    #[test]
+    fn names_partitions_and_event_files() {
+        assert_eq!("date=2024-06-01", date_partition(1_717_243_200_000));
+        assert_eq!(
+            Some((2, 41)),
+            parse_event_file(Path::new(
+                "events/date=2024-06-01/part-p2-00000000000000000007-00000000000000000041.parquet"
+            ))
+        );
+        assert_eq!(
+            None,
+            parse_event_file(Path::new("part-p2-7-41.parquet.tmp"))
+        );
+    }
+}
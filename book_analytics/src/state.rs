@@ -0,0 +1,60 @@
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StateError {
+    #[error("Sled error")]
+    SledError(#[from] sled::Error),
+
+    #[error("State serialization error")]
+    SerializationError(#[from] serde_json::Error),
+}
+
+/// A durable key-value store for analytics state. A successful `put` survives a crash.
+pub trait StateStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StateError>;
+
+    fn put(&self, key: &str, value: Vec<u8>) -> Result<(), StateError>;
+}
+
+/// Keeps state for as long as the process lives, for tests.
+#[derive(Default)]
+pub struct InMemoryStore {
+    entries: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl StateStore for InMemoryStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StateError> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: Vec<u8>) -> Result<(), StateError> {
+        self.entries.lock().unwrap().insert(key.to_owned(), value);
+        Ok(())
+    }
+}
+
+pub struct SledStore {
+    db: sled::Db,
+}
+
+impl SledStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        Ok(Self {
+            db: sled::open(path)?,
+        })
+    }
+}
+
+impl StateStore for SledStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StateError> {
+        Ok(self.db.get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn put(&self, key: &str, value: Vec<u8>) -> Result<(), StateError> {
+        self.db.insert(key, value)?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Display,
+    EnumIter,
+    EnumString,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Granularity {
+    pub fn millis(&self) -> i64 {
+        match self {
+            Self::Minute => 60_000,
+            Self::Hour => 3_600_000,
+            Self::Day => 86_400_000,
+        }
+    }
+}
+
+/// A window of `size`, starting every `hop_ms`. Windows are aligned to the epoch,
+/// so day windows run from midnight UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct WindowSpec {
+    pub size: Granularity,
+    pub hop_ms: i64,
+}
+
+impl WindowSpec {
+    /// Non-overlapping windows, each event counted once.
+    pub fn tumbling(size: Granularity) -> Self {
+        Self {
+            size,
+            hop_ms: size.millis(),
+        }
+    }
+
+    /// Overlapping windows, each event counted in every window covering it.
+    pub fn hopping(size: Granularity, hop: Duration) -> Self {
+        let hop_ms = hop.as_millis() as i64;
+        assert!(
+            hop_ms > 0 && hop_ms <= size.millis(),
+            "hop must be positive and at most the window size"
+        );
+        Self { size, hop_ms }
+    }
+
+    pub fn is_tumbling(&self) -> bool {
+        self.hop_ms == self.size.millis()
+    }
+
+    pub fn end(&self, start: i64) -> i64 {
+        start + self.size.millis()
+    }
+
+    /// Starts of the windows containing `timestamp`, latest first.
+    pub fn windows(&self, timestamp: i64) -> impl Iterator<Item = i64> + '_ {
+        let last = timestamp.div_euclid(self.hop_ms) * self.hop_ms;
+        (0..)
+            .map(move |i| last - i * self.hop_ms)
+            .take_while(move |start| self.end(*start) > timestamp)
+    }
+
+    /// The earliest window start whose window ends after `timestamp`.
+    pub fn first_ending_after(&self, timestamp: i64) -> i64 {
+        (timestamp - self.size.millis()).div_euclid(self.hop_ms) * self.hop_ms + self.hop_ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tumbling_windows_contain_each_timestamp_once() {
+        let spec = WindowSpec::tumbling(Granularity::Minute);
+        assert_eq!(vec![60_000], spec.windows(90_000).collect::<Vec<_>>());
+        assert_eq!(vec![-60_000], spec.windows(-1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn hopping_windows_overlap() {
+        let spec = WindowSpec::hopping(Granularity::Hour, Duration::from_secs(15 * 60));
+        assert_eq!(
+            vec![3_600_000, 2_700_000, 1_800_000, 900_000],
+            spec.windows(3_600_000).collect::<Vec<_>>()
+        );
+        assert_eq!(3_600_000, spec.first_ending_after(6_300_000));
+    }
+}
@@ -1,12 +1,34 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20240520_000001_create_processed_event;
+mod m20240601_000001_add_book_created_at;
+mod m20240615_000001_add_book_search;
+mod m20240701_000001_create_catalog;
+mod m20240715_000001_add_book_metadata;
+mod m20240801_000001_unique_normalized_isbn;
+mod m20240815_000001_add_book_version;
+mod m20240901_000001_add_book_deleted_at;
+mod m20240915_000001_create_book_history;
+mod m20241101_000001_create_import_report;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20240520_000001_create_processed_event::Migration),
+            Box::new(m20240601_000001_add_book_created_at::Migration),
+            Box::new(m20240615_000001_add_book_search::Migration),
+            Box::new(m20240701_000001_create_catalog::Migration),
+            Box::new(m20240715_000001_add_book_metadata::Migration),
+            Box::new(m20240801_000001_unique_normalized_isbn::Migration),
+            Box::new(m20240815_000001_add_book_version::Migration),
+            Box::new(m20240901_000001_add_book_deleted_at::Migration),
+            Box::new(m20240915_000001_create_book_history::Migration),
+            Box::new(m20241101_000001_create_import_report::Migration),
+        ]
     }
 }
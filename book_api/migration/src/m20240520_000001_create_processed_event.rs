@@ -0,0 +1,100 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Events consumers handled, see `database::processed_events`.
+        manager
+            .create_table(
+                Table::create()
+                    .table(ProcessedEvent::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ProcessedEvent::ConsumerGroup)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ProcessedEvent::EventId).text().not_null())
+                    .col(
+                        ColumnDef::new(ProcessedEvent::ProcessedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ProcessedEvent::ConsumerGroup)
+                            .col(ProcessedEvent::EventId),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("processed_event_processed_at_idx")
+                    .table(ProcessedEvent::Table)
+                    .col(ProcessedEvent::ProcessedAt)
+                    .to_owned(),
+            )
+            .await?;
+        // Records consumers couldn't decode and moved past.
+        manager
+            .create_table(
+                Table::create()
+                    .table(DeadLetterEvent::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DeadLetterEvent::ConsumerGroup)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(DeadLetterEvent::EventId).text().not_null())
+                    .col(ColumnDef::new(DeadLetterEvent::Error).text().not_null())
+                    .col(ColumnDef::new(DeadLetterEvent::Payload).binary())
+                    .col(
+                        ColumnDef::new(DeadLetterEvent::DeadLetteredAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(DeadLetterEvent::ConsumerGroup)
+                            .col(DeadLetterEvent::EventId),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DeadLetterEvent::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ProcessedEvent::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum ProcessedEvent {
+    Table,
+    ConsumerGroup,
+    EventId,
+    ProcessedAt,
+}
+
+#[derive(Iden)]
+enum DeadLetterEvent {
+    Table,
+    ConsumerGroup,
+    EventId,
+    Error,
+    Payload,
+    DeadLetteredAt,
+}
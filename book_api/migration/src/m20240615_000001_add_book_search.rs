@@ -0,0 +1,39 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared("CREATE EXTENSION IF NOT EXISTS pg_trgm")
+            .await?;
+        // Titles are stemmed, ISBNs are kept as they are and weigh less.
+        db.execute_unprepared(
+            "ALTER TABLE book ADD COLUMN search tsvector GENERATED ALWAYS AS (\
+                 setweight(to_tsvector('english', coalesce(title, '')), 'A') || \
+                 setweight(to_tsvector('simple', coalesce(isbn, '')), 'B')\
+             ) STORED",
+        )
+        .await?;
+        db.execute_unprepared("CREATE INDEX book_search_idx ON book USING GIN (search)")
+            .await?;
+        db.execute_unprepared(
+            "CREATE INDEX book_title_trgm_idx ON book USING GIN (title gin_trgm_ops)",
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared("DROP INDEX IF EXISTS book_title_trgm_idx")
+            .await?;
+        db.execute_unprepared("DROP INDEX IF EXISTS book_search_idx")
+            .await?;
+        db.execute_unprepared("ALTER TABLE book DROP COLUMN IF EXISTS search")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Author::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Author::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Author::Name).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(Publisher::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Publisher::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Publisher::Name)
+                            .string()
+                            .unique_key()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(Genre::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Genre::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Genre::Name).string().unique_key().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Book::Table)
+                    .add_column(ColumnDef::new(Book::PublisherId).integer().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_book_publisher")
+                            .from_tbl(Book::Table)
+                            .from_col(Book::PublisherId)
+                            .to_tbl(Publisher::Table)
+                            .to_col(Publisher::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(BookAuthor::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(BookAuthor::BookId).integer().not_null())
+                    .col(ColumnDef::new(BookAuthor::AuthorId).integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(BookAuthor::BookId)
+                            .col(BookAuthor::AuthorId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_book_author_book")
+                            .from(BookAuthor::Table, BookAuthor::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_book_author_author")
+                            .from(BookAuthor::Table, BookAuthor::AuthorId)
+                            .to(Author::Table, Author::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(BookGenre::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(BookGenre::BookId).integer().not_null())
+                    .col(ColumnDef::new(BookGenre::GenreId).integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(BookGenre::BookId)
+                            .col(BookGenre::GenreId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_book_genre_book")
+                            .from(BookGenre::Table, BookGenre::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_book_genre_genre")
+                            .from(BookGenre::Table, BookGenre::GenreId)
+                            .to(Genre::Table, Genre::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(BookGenre::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(BookAuthor::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Book::Table)
+                    .drop_foreign_key(Alias::new("fk_book_publisher"))
+                    .drop_column(Book::PublisherId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Genre::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Publisher::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Author::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Book {
+    Table,
+    Id,
+    PublisherId,
+}
+
+#[derive(Iden)]
+enum Author {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(Iden)]
+enum Publisher {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(Iden)]
+enum Genre {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(Iden)]
+enum BookAuthor {
+    Table,
+    BookId,
+    AuthorId,
+}
+
+#[derive(Iden)]
+enum BookGenre {
+    Table,
+    BookId,
+    GenreId,
+}
@@ -0,0 +1,55 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Book::Table)
+                    .add_column(ColumnDef::new(Book::PublishedOn).date().null())
+                    .add_column(ColumnDef::new(Book::Language).string_len(35).null())
+                    .add_column(
+                        ColumnDef::new(Book::PageCount)
+                            .integer()
+                            .null()
+                            .check(Expr::col(Book::PageCount).gt(0)),
+                    )
+                    .add_column(ColumnDef::new(Book::Edition).string().null())
+                    .add_column(ColumnDef::new(Book::Description).text().null())
+                    .add_column(ColumnDef::new(Book::CoverUrl).string_len(2048).null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Book::Table)
+                    .drop_column(Book::PublishedOn)
+                    .drop_column(Book::Language)
+                    .drop_column(Book::PageCount)
+                    .drop_column(Book::Edition)
+                    .drop_column(Book::Description)
+                    .drop_column(Book::CoverUrl)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Book {
+    Table,
+    PublishedOn,
+    Language,
+    PageCount,
+    Edition,
+    Description,
+    CoverUrl,
+}
@@ -0,0 +1,90 @@
+use common::isbn;
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Statement, Value};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Rows updated per statement while filling in the normalized ISBNs.
+const BATCH_SIZE: usize = 1000;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared("ALTER TABLE book DROP CONSTRAINT IF EXISTS book_title_key")
+            .await?;
+        db.execute_unprepared("CREATE INDEX book_title_idx ON book (title)")
+            .await?;
+        // Postgres can't compute ISBN-13s, so the column is written by the API with
+        // `common::isbn::canonical`, which the fill below uses too.
+        db.execute_unprepared("ALTER TABLE book ADD COLUMN isbn_normalized varchar NULL")
+            .await?;
+        let books = db
+            .query_all(Statement::from_string(
+                manager.get_database_backend(),
+                "SELECT id, isbn FROM book ORDER BY id".to_owned(),
+            ))
+            .await?
+            .iter()
+            .map(|row| {
+                Ok((
+                    row.try_get::<i32>("", "id")?,
+                    row.try_get::<String>("", "isbn")?,
+                ))
+            })
+            .collect::<Result<Vec<_>, DbErr>>()?;
+        for batch in books.chunks(BATCH_SIZE) {
+            let rows = (0..batch.len())
+                .map(|i| format!("(${}::integer, ${}::varchar)", 2 * i + 1, 2 * i + 2))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let values = batch
+                .iter()
+                .flat_map(|(id, isbn)| [Value::from(*id), Value::from(isbn::canonical(isbn))]);
+            db.execute(Statement::from_sql_and_values(
+                manager.get_database_backend(),
+                format!(
+                    "UPDATE book SET isbn_normalized = canonical.isbn \
+                     FROM (VALUES {}) AS canonical (id, isbn) WHERE book.id = canonical.id",
+                    rows
+                ),
+                values,
+            ))
+            .await?;
+        }
+        db.execute_unprepared("ALTER TABLE book ALTER COLUMN isbn_normalized SET NOT NULL")
+            .await?;
+        // Rows already sharing an ISBN can't be told apart safely here, so all but the
+        // first are flagged and left out of the unique index until they are fixed.
+        db.execute_unprepared("ALTER TABLE book ADD COLUMN isbn_duplicate_of integer NULL")
+            .await?;
+        db.execute_unprepared(
+            "UPDATE book SET isbn_duplicate_of = first.id \
+             FROM (SELECT isbn_normalized, min(id) AS id FROM book GROUP BY isbn_normalized) first \
+             WHERE book.isbn_normalized = first.isbn_normalized AND book.id <> first.id",
+        )
+        .await?;
+        db.execute_unprepared(
+            "CREATE UNIQUE INDEX book_isbn_normalized_key ON book (isbn_normalized) \
+             WHERE isbn_duplicate_of IS NULL",
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared("DROP INDEX IF EXISTS book_isbn_normalized_key")
+            .await?;
+        db.execute_unprepared("ALTER TABLE book DROP COLUMN IF EXISTS isbn_duplicate_of")
+            .await?;
+        db.execute_unprepared("ALTER TABLE book DROP COLUMN IF EXISTS isbn_normalized")
+            .await?;
+        db.execute_unprepared("DROP INDEX IF EXISTS book_title_idx")
+            .await?;
+        db.execute_unprepared("ALTER TABLE book ADD CONSTRAINT book_title_key UNIQUE (title)")
+            .await?;
+        Ok(())
+    }
+}
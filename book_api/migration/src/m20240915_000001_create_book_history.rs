@@ -0,0 +1,101 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// A `create` entry for every book, holding its tracked fields as the API serializes
+/// them, nulls left out, dated when the book was created where that is known.
+const BASELINE: &str = "\
+    INSERT INTO book_history (book_id, version, operation, actor, changed_at, diff) \
+    SELECT b.id, b.version, 'create', 'migration', coalesce(b.created_at, now()), \
+        coalesce((SELECT jsonb_object_agg(field.key, \
+            jsonb_build_object('from', null, 'to', field.value)) \
+            FROM jsonb_each(jsonb_build_object(\
+                'title', b.title, \
+                'isbn', b.isbn, \
+                'publisher_id', b.publisher_id, \
+                'author_ids', coalesce((SELECT jsonb_agg(a.author_id ORDER BY a.author_id) \
+                    FROM book_author a WHERE a.book_id = b.id), '[]'::jsonb), \
+                'genre_ids', coalesce((SELECT jsonb_agg(g.genre_id ORDER BY g.genre_id) \
+                    FROM book_genre g WHERE g.book_id = b.id), '[]'::jsonb), \
+                'published_on', b.published_on, \
+                'language', b.language, \
+                'page_count', b.page_count, \
+                'edition', b.edition, \
+                'description', b.description, \
+                'cover_url', b.cover_url, \
+                'deleted_at', b.deleted_at)) AS field \
+            WHERE field.value <> 'null'::jsonb), '{}'::jsonb) \
+    FROM book b";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // No foreign key to `book`, so that history outlives purged books.
+        manager
+            .create_table(
+                Table::create()
+                    .table(BookHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(BookHistory::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(BookHistory::BookId).integer().not_null())
+                    .col(ColumnDef::new(BookHistory::Version).integer().not_null())
+                    .col(
+                        ColumnDef::new(BookHistory::Operation)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(BookHistory::Actor).string().not_null())
+                    .col(
+                        ColumnDef::new(BookHistory::ChangedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(BookHistory::Diff).json_binary().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("book_history_book_id_idx")
+                    .table(BookHistory::Table)
+                    .col(BookHistory::BookId)
+                    .col(BookHistory::Id)
+                    .to_owned(),
+            )
+            .await?;
+        // Without a `create` entry, the history of the books already there couldn't
+        // be replayed.
+        manager
+            .get_connection()
+            .execute_unprepared(BASELINE)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(BookHistory::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum BookHistory {
+    Table,
+    Id,
+    BookId,
+    Version,
+    Operation,
+    Actor,
+    ChangedAt,
+    Diff,
+}
@@ -0,0 +1,142 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportReport::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImportReport::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImportReport::Actor).string().not_null())
+                    .col(ColumnDef::new(ImportReport::DryRun).boolean().not_null())
+                    .col(
+                        ColumnDef::new(ImportReport::Total)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ImportReport::Succeeded)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ImportReport::Failed)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(ImportReport::Error).string().null())
+                    .col(
+                        ColumnDef::new(ImportReport::StartedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(ImportReport::FinishedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportReportRow::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImportReportRow::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ImportReportRow::ReportId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImportReportRow::Line).integer().not_null())
+                    .col(ColumnDef::new(ImportReportRow::Isbn).string().null())
+                    .col(
+                        ColumnDef::new(ImportReportRow::Status)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImportReportRow::BookId).integer().null())
+                    .col(
+                        ColumnDef::new(ImportReportRow::Errors)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_import_report_row_report")
+                            .from(ImportReportRow::Table, ImportReportRow::ReportId)
+                            .to(ImportReport::Table, ImportReport::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("import_report_row_report_id_idx")
+                    .table(ImportReportRow::Table)
+                    .col(ImportReportRow::ReportId)
+                    .col(ImportReportRow::Line)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImportReportRow::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ImportReport::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum ImportReport {
+    Table,
+    Id,
+    Actor,
+    DryRun,
+    Total,
+    Succeeded,
+    Failed,
+    Error,
+    StartedAt,
+    FinishedAt,
+}
+
+#[derive(Iden)]
+enum ImportReportRow {
+    Table,
+    Id,
+    ReportId,
+    Line,
+    Isbn,
+    Status,
+    BookId,
+    Errors,
+}
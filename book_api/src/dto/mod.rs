@@ -1,9 +1,307 @@
 use derive_builder::Builder;
+use sea_orm::prelude::{Date, DateTimeWithTimeZone, DeriveActiveEnum, EnumIter};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Builder)]
+const MAX_PAGE_COUNT: i32 = 100_000;
+const MAX_EDITION_LEN: usize = 100;
+const MAX_DESCRIPTION_LEN: usize = 10_000;
+const MAX_URL_LEN: usize = 2048;
+
+#[derive(Serialize, Deserialize, Builder, Clone, Debug)]
 pub struct Book {
     pub id: i32,
     pub title: String,
     pub isbn: String,
+    #[builder(default)]
+    pub publisher_id: Option<i32>,
+    #[builder(default)]
+    pub author_ids: Vec<i32>,
+    #[builder(default)]
+    pub genre_ids: Vec<i32>,
+    #[builder(default)]
+    pub metadata: BookMetadata,
+    pub version: i32,
+}
+
+/// A book matching a search, with its title highlighted where it matched.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BookSearchHit {
+    pub id: i32,
+    pub title: String,
+    pub isbn: String,
+    pub rank: f64,
+    pub snippet: String,
+}
+
+/// Enough of a book to tell which one it is.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BookSummary {
+    pub id: i32,
+    pub title: String,
+    pub isbn: String,
+    /// Set for soft-deleted books.
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+}
+
+/// Which books a listing includes, by whether they are soft-deleted.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletedFilter {
+    #[default]
+    Include,
+    Exclude,
+    Only,
+}
+
+/// Books sharing an ISBN in its canonical form, oldest first.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateIsbn {
+    pub isbn_normalized: String,
+    pub books: Vec<BookSummary>,
+}
+
+/// The catalog tables books refer to, whose entries are only a name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CatalogKind {
+    Author,
+    Publisher,
+    Genre,
+}
+
+impl CatalogKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CatalogKind::Author => "author",
+            CatalogKind::Publisher => "publisher",
+            CatalogKind::Genre => "genre",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub id: i32,
+    pub name: String,
+}
+
+/// A book with its publisher, authors and genres.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BookDetails {
+    pub id: i32,
+    pub title: String,
+    pub isbn: String,
+    pub publisher: Option<CatalogEntry>,
+    pub authors: Vec<CatalogEntry>,
+    pub genres: Vec<CatalogEntry>,
+    #[serde(flatten)]
+    pub metadata: BookMetadata,
+    pub version: i32,
+}
+
+impl From<BookDetails> for Book {
+    fn from(details: BookDetails) -> Self {
+        let references = details.references();
+        Self {
+            id: details.id,
+            title: details.title,
+            isbn: details.isbn,
+            publisher_id: references.publisher_id,
+            author_ids: references.author_ids,
+            genre_ids: references.genre_ids,
+            metadata: details.metadata,
+            version: details.version,
+        }
+    }
+}
+
+impl BookDetails {
+    pub fn references(&self) -> BookReferences {
+        BookReferences {
+            publisher_id: self.publisher.as_ref().map(|publisher| publisher.id),
+            author_ids: self.authors.iter().map(|author| author.id).collect(),
+            genre_ids: self.genres.iter().map(|genre| genre.id).collect(),
+        }
+    }
+}
+
+/// Catalog entries a new book refers to.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BookReferences {
+    #[serde(default)]
+    pub publisher_id: Option<i32>,
+    #[serde(default)]
+    pub author_ids: Vec<i32>,
+    #[serde(default)]
+    pub genre_ids: Vec<i32>,
+}
+
+/// A book to create, as it is imported.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NewBook {
+    pub title: String,
+    pub isbn: String,
+    #[serde(flatten)]
+    pub references: BookReferences,
+    #[serde(flatten)]
+    pub metadata: BookMetadata,
+}
+
+/// The fields of a book its history tracks, with catalog entries by id.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BookRecord {
+    pub title: String,
+    pub isbn: String,
+    #[serde(flatten)]
+    pub references: BookReferences,
+    #[serde(flatten)]
+    pub metadata: BookMetadata,
+    #[serde(default)]
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+}
+
+/// The kinds of change recorded in a book's history, stored by their lowercase name.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[serde(rename_all = "lowercase")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum BookOperation {
+    #[sea_orm(string_value = "create")]
+    Create,
+    #[sea_orm(string_value = "update")]
+    Update,
+    #[sea_orm(string_value = "delete")]
+    Delete,
+    #[sea_orm(string_value = "restore")]
+    Restore,
+    #[sea_orm(string_value = "purge")]
+    Purge,
+}
+
+/// One change of a book: who made it, when, and the fields it changed as
+/// `{"field": {"from": old, "to": new}}`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BookHistoryEntry {
+    pub version: i32,
+    pub operation: BookOperation,
+    pub actor: String,
+    pub changed_at: DateTimeWithTimeZone,
+    pub diff: serde_json::Value,
+}
+
+/// A book as it was at `as_of`, rebuilt from its history.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BookAsOf {
+    pub id: i32,
+    pub version: i32,
+    pub as_of: DateTimeWithTimeZone,
+    #[serde(flatten)]
+    pub record: BookRecord,
+}
+
+/// Optional descriptive fields of a book.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BookMetadata {
+    #[serde(default)]
+    pub published_on: Option<Date>,
+    /// A BCP 47 language tag, such as `en` or `pt-BR`.
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub page_count: Option<i32>,
+    #[serde(default)]
+    pub edition: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub cover_url: Option<String>,
+}
+
+impl BookMetadata {
+    /// Every problem with the fields, empty when they are valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Some(language) = &self.language {
+            if !is_language_tag(language) {
+                errors.push(format!("language '{}' is not a BCP 47 tag", language));
+            }
+        }
+        if let Some(page_count) = self.page_count {
+            if !(1..=MAX_PAGE_COUNT).contains(&page_count) {
+                errors.push(format!(
+                    "page_count must be between 1 and {}",
+                    MAX_PAGE_COUNT
+                ));
+            }
+        }
+        if let Some(edition) = &self.edition {
+            if edition.trim().is_empty() || edition.chars().count() > MAX_EDITION_LEN {
+                errors.push(format!(
+                    "edition must be 1 to {} characters",
+                    MAX_EDITION_LEN
+                ));
+            }
+        }
+        if let Some(description) = &self.description {
+            if description.chars().count() > MAX_DESCRIPTION_LEN {
+                errors.push(format!(
+                    "description must be at most {} characters",
+                    MAX_DESCRIPTION_LEN
+                ));
+            }
+        }
+        if let Some(cover_url) = &self.cover_url {
+            let host = cover_url
+                .strip_prefix("https://")
+                .or_else(|| cover_url.strip_prefix("http://"))
+                .and_then(|rest| rest.split('/').next())
+                .unwrap_or_default();
+            if host.is_empty()
+                || cover_url.len() > MAX_URL_LEN
+                || cover_url.contains(char::is_whitespace)
+            {
+                errors.push("cover_url must be an http or https URL".to_owned());
+            }
+        }
+        errors
+    }
+}
+
+/// Whether `tag` has the shape of a BCP 47 tag: a 2 to 3, or 5 to 8, letter language
+/// subtag, then subtags of 1 to 8 letters and digits. Subtags aren't checked
+/// against the registry.
+fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let language = subtags.next().unwrap_or_default();
+    matches!(language.len(), 2..=3 | 5..=8)
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_tags() {
+        assert!(is_language_tag("en"));
+        assert!(is_language_tag("pt-BR"));
+        assert!(is_language_tag("zh-Hant-TW"));
+        assert!(!is_language_tag("english language"));
+        assert!(!is_language_tag("e"));
+        assert!(!is_language_tag("en--US"));
+    }
+
+    #[test]
+    fn test_validate_metadata() {
+        assert!(BookMetadata::default().validate().is_empty());
+        let metadata = BookMetadata {
+            language: Some("en-GB".to_owned()),
+            page_count: Some(0),
+            edition: Some(" ".to_owned()),
+            cover_url: Some("ftp://covers/1.jpg".to_owned()),
+            ..BookMetadata::default()
+        };
+        assert_eq!(3, metadata.validate().len());
+    }
 }
@@ -8,12 +8,79 @@ use serde::{Deserialize, Serialize};
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
-    #[sea_orm(unique)]
     pub title: String,
     pub isbn: String,
+    /// `None` for books created before the column was added.
+    pub created_at: Option<DateTimeWithTimeZone>,
+    pub publisher_id: Option<i32>,
+    pub published_on: Option<Date>,
+    pub language: Option<String>,
+    pub page_count: Option<i32>,
+    pub edition: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub description: Option<String>,
+    pub cover_url: Option<String>,
+    /// `isbn` in the form of `common::isbn::canonical`, and unique unless flagged as a
+    /// duplicate.
+    pub isbn_normalized: String,
+    /// The book that had the ISBN first, for duplicates that predate the unique index.
+    pub isbn_duplicate_of: Option<i32>,
+    /// Starts at 1 and goes up by one with every write.
+    pub version: i32,
+    /// When the book was soft-deleted; it's purged once this is older than the retention.
+    pub deleted_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::book_author::Entity")]
+    BookAuthor,
+    #[sea_orm(has_many = "super::book_genre::Entity")]
+    BookGenre,
+    #[sea_orm(
+        belongs_to = "super::publisher::Entity",
+        from = "Column::PublisherId",
+        to = "super::publisher::Column::Id",
+        on_update = "NoAction",
+        on_delete = "SetNull"
+    )]
+    Publisher,
+}
+
+impl Related<super::book_author::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BookAuthor.def()
+    }
+}
+
+impl Related<super::book_genre::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BookGenre.def()
+    }
+}
+
+impl Related<super::publisher::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Publisher.def()
+    }
+}
+
+impl Related<super::author::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::book_author::Relation::Author.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::book_author::Relation::Book.def().rev())
+    }
+}
+
+impl Related<super::genre::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::book_genre::Relation::Genre.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::book_genre::Relation::Book.def().rev())
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -2,4 +2,12 @@
 
 pub mod prelude;
 
+pub mod author;
 pub mod book;
+pub mod book_author;
+pub mod book_genre;
+pub mod book_history;
+pub mod genre;
+pub mod import_report;
+pub mod import_report_row;
+pub mod publisher;
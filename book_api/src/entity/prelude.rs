@@ -1,3 +1,11 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+pub use super::author::Entity as Author;
 pub use super::book::Entity as Book;
+pub use super::book_author::Entity as BookAuthor;
+pub use super::book_genre::Entity as BookGenre;
+pub use super::book_history::Entity as BookHistory;
+pub use super::genre::Entity as Genre;
+pub use super::import_report::Entity as ImportReport;
+pub use super::import_report_row::Entity as ImportReportRow;
+pub use super::publisher::Entity as Publisher;
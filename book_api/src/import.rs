@@ -0,0 +1,668 @@
+//! Bulk import of books from CSV or JSON Lines files, behind both
+//! `POST /api/books/import` and the `import` command.
+
+use std::{collections::HashMap, path::Path};
+
+use chrono::{Duration, Utc};
+use clap::ValueEnum;
+use common::isbn;
+use sea_orm::prelude::{DateTimeWithTimeZone, DeriveActiveEnum, EnumIter};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tracing::error;
+
+use crate::{
+    dto::NewBook,
+    service::{Service, ServiceError},
+};
+
+/// Rows created per transaction.
+const BATCH_SIZE: usize = 500;
+
+/// Days reports are kept for download. Older ones are dropped as new imports start.
+const REPORT_RETENTION_DAYS: i64 = 30;
+
+/// The columns a CSV file may have, in any order.
+const CSV_COLUMNS: [&str; 11] = [
+    "title",
+    "isbn",
+    "publisher_id",
+    "author_ids",
+    "genre_ids",
+    "published_on",
+    "language",
+    "page_count",
+    "edition",
+    "description",
+    "cover_url",
+];
+
+const REQUIRED_COLUMNS: [&str; 2] = ["title", "isbn"];
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// A header row naming the columns, then one book per record.
+    Csv,
+    /// One JSON book per line, shaped like the body of `POST /api/books`.
+    Jsonl,
+}
+
+impl ImportFormat {
+    /// The format the extension of `file_name` stands for.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "csv" => Some(ImportFormat::Csv),
+            "jsonl" | "ndjson" => Some(ImportFormat::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("Unknown file format, it must be csv or jsonl")]
+    UnknownFormat,
+
+    #[error("Invalid CSV header: {0}")]
+    InvalidHeader(String),
+
+    #[error("Service error")]
+    ServiceError(#[from] ServiceError),
+
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+
+    #[error("CSV error")]
+    CsvError(#[from] csv::Error),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[serde(rename_all = "snake_case")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum RowStatus {
+    #[sea_orm(string_value = "imported")]
+    Imported,
+    /// Would have been imported, in a dry run.
+    #[sea_orm(string_value = "valid")]
+    Valid,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+}
+
+impl RowStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RowStatus::Imported => "imported",
+            RowStatus::Valid => "valid",
+            RowStatus::Failed => "failed",
+        }
+    }
+}
+
+/// What became of one row of an imported file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ImportRow {
+    /// The line the row starts on, counting from 1.
+    pub line: usize,
+    pub isbn: Option<String>,
+    pub status: RowStatus,
+    /// The created book, never set in a dry run.
+    pub book_id: Option<i32>,
+    pub errors: Vec<String>,
+}
+
+/// The totals of an import, kept with its rows for download.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ImportReport {
+    pub id: i32,
+    pub dry_run: bool,
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Why the import stopped early, when a batch couldn't be created. The rows of
+    /// that batch and of the rest of the file are failed.
+    pub error: Option<String>,
+    pub started_at: DateTimeWithTimeZone,
+    /// Not set while the import runs, or when it never ended.
+    pub finished_at: Option<DateTimeWithTimeZone>,
+}
+
+/// `rows` as CSV, with their errors joined by `; `.
+pub fn report_csv(rows: &[ImportRow]) -> Result<Vec<u8>, ImportError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["line", "isbn", "status", "book_id", "errors"])?;
+    for row in rows {
+        writer.write_record([
+            row.line.to_string(),
+            row.isbn.clone().unwrap_or_default(),
+            row.status.name().to_owned(),
+            row.book_id.map(|id| id.to_string()).unwrap_or_default(),
+            row.errors.join("; "),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| ImportError::IoError(e.into_error()))
+}
+
+/// Imports a file fed to it in chunks of any size. Neither the file nor its report
+/// is held in memory: rows go to the stored report as they are done, and only the
+/// ISBNs seen so far are kept. Valid rows are created in batches of `BATCH_SIZE`,
+/// each in its own transaction, so a failing import keeps the batches before it.
+pub struct Importer {
+    service: Service,
+    actor: String,
+    format: ImportFormat,
+    dry_run: bool,
+    /// What follows the last line break read.
+    pending: Vec<u8>,
+    line: usize,
+    /// A CSV record with a quoted field still open, and the line it starts on.
+    record: Option<(usize, String)>,
+    header: Option<Vec<String>>,
+    /// The line of each ISBN, in its ISBN-13 form, so that a file can't hold one
+    /// twice, even as an ISBN-10 and an ISBN-13.
+    isbns: HashMap<String, usize>,
+    batch: Vec<(usize, NewBook)>,
+    /// Created along with its first rows, so that a file rejected outright leaves none.
+    report: Option<ImportReport>,
+    /// Rows not added to the report yet.
+    rows: Vec<ImportRow>,
+    total: usize,
+    failed: usize,
+    /// Why rows stopped being imported, once a batch couldn't be.
+    error: Option<String>,
+}
+
+impl Importer {
+    pub fn new(service: Service, actor: String, format: ImportFormat, dry_run: bool) -> Self {
+        Self {
+            service,
+            actor,
+            format,
+            dry_run,
+            pending: Vec::new(),
+            line: 0,
+            record: None,
+            header: None,
+            isbns: HashMap::new(),
+            batch: Vec::new(),
+            report: None,
+            rows: Vec::new(),
+            total: 0,
+            failed: 0,
+            error: None,
+        }
+    }
+
+    /// Reads the next bytes of the file.
+    pub async fn push(&mut self, chunk: &[u8]) -> Result<(), ImportError> {
+        self.pending.extend_from_slice(chunk);
+        let pending = std::mem::take(&mut self.pending);
+        let mut lines: Vec<&[u8]> = pending.split(|byte| *byte == b'\n').collect();
+        let rest = lines.pop().unwrap_or_default().to_vec();
+        for line in lines {
+            self.read_line(line).await?;
+            if self.rows.len() >= BATCH_SIZE {
+                self.write_rows().await?;
+            }
+        }
+        self.pending = rest;
+        Ok(())
+    }
+
+    /// Imports the rows left once the whole file was pushed, and completes the report.
+    pub async fn finish(mut self) -> Result<ImportReport, ImportError> {
+        let rest = std::mem::take(&mut self.pending);
+        if !rest.is_empty() {
+            self.read_line(&rest).await?;
+        }
+        if let Some((line, _)) = self.record.take() {
+            self.fail(line, None, vec!["unterminated quoted field".to_owned()]);
+        }
+        self.flush().await?;
+        self.write_rows().await?;
+        let mut report = self.report().await?.clone();
+        report.total = self.total;
+        report.succeeded = self.total - self.failed;
+        report.failed = self.failed;
+        report.error = self.error.take();
+        Ok(self.service.finish_import_report(&report).await?)
+    }
+
+    /// The report, started on the first call.
+    async fn report(&mut self) -> Result<&ImportReport, ImportError> {
+        let report = match self.report.take() {
+            Some(report) => report,
+            None => {
+                let expired_before = (Utc::now() - Duration::days(REPORT_RETENTION_DAYS)).into();
+                self.service
+                    .create_import_report(&self.actor, self.dry_run, expired_before)
+                    .await?
+            }
+        };
+        Ok(self.report.insert(report))
+    }
+
+    async fn write_rows(&mut self) -> Result<(), ImportError> {
+        let report_id = self.report().await?.id;
+        let rows = std::mem::take(&mut self.rows);
+        self.service.add_import_rows(report_id, &rows).await?;
+        Ok(())
+    }
+
+    async fn read_line(&mut self, bytes: &[u8]) -> Result<(), ImportError> {
+        self.line += 1;
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        let Ok(line) = std::str::from_utf8(bytes) else {
+            let start = self.record.take().map_or(self.line, |(start, _)| start);
+            self.fail(start, None, vec!["not valid UTF-8".to_owned()]);
+            return Ok(());
+        };
+        let line = if self.line == 1 {
+            line.trim_start_matches('\u{feff}')
+        } else {
+            line
+        };
+        match self.format {
+            ImportFormat::Csv => self.read_csv_line(line).await,
+            ImportFormat::Jsonl if line.trim().is_empty() => Ok(()),
+            ImportFormat::Jsonl => match serde_json::from_str::<NewBook>(line) {
+                Ok(book) => self.add(self.line, book).await,
+                Err(e) => {
+                    let isbn = serde_json::from_str::<Value>(line)
+                        .ok()
+                        .and_then(|row| Some(row.get("isbn")?.as_str()?.to_owned()));
+                    self.fail(self.line, isbn, vec![e.to_string()]);
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    async fn read_csv_line(&mut self, line: &str) -> Result<(), ImportError> {
+        let (start, record) = match self.record.take() {
+            Some((start, mut record)) => {
+                record.push('\n');
+                record.push_str(line);
+                (start, record)
+            }
+            None if line.trim().is_empty() => return Ok(()),
+            None => (self.line, line.to_owned()),
+        };
+        // Quotes only come in pairs once every quoted field is closed.
+        if record.matches('"').count() % 2 == 1 {
+            self.record = Some((start, record));
+            return Ok(());
+        }
+        let fields = match parse_csv_record(&record) {
+            Ok(fields) => fields,
+            Err(e) => {
+                self.fail(start, None, vec![e.to_string()]);
+                return Ok(());
+            }
+        };
+        let Some(header) = &self.header else {
+            self.header = Some(csv_header(&fields)?);
+            return Ok(());
+        };
+        let isbn = header
+            .iter()
+            .position(|column| column == "isbn")
+            .and_then(|i| fields.get(i))
+            .map(|isbn| isbn.trim().to_owned());
+        match csv_book(header, &fields) {
+            Ok(book) => self.add(start, book).await,
+            Err(errors) => {
+                self.fail(start, isbn, errors);
+                Ok(())
+            }
+        }
+    }
+
+    /// Queues a valid row, importing the batch once it is full.
+    async fn add(&mut self, line: usize, book: NewBook) -> Result<(), ImportError> {
+        if let Some(error) = &self.error {
+            let errors = vec![format!("not imported, the import stopped: {}", error)];
+            self.fail(line, Some(book.isbn), errors);
+            return Ok(());
+        }
+        let errors = validate(line, &book, &mut self.isbns);
+        if !errors.is_empty() {
+            self.fail(line, Some(book.isbn), errors);
+            return Ok(());
+        }
+        self.batch.push((line, book));
+        if self.batch.len() >= BATCH_SIZE {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ImportError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let (lines, books): (Vec<usize>, Vec<NewBook>) =
+            std::mem::take(&mut self.batch).into_iter().unzip();
+        let isbns: Vec<String> = books.iter().map(|book| book.isbn.clone()).collect();
+        let results = match self
+            .service
+            .import_books(&self.actor, books, self.dry_run)
+            .await
+        {
+            Ok(results) => results,
+            // The rest of the file is still read, to report every row.
+            Err(e) => {
+                let message = error_message(&e);
+                error!("Import stopped at line {}: {}", lines[0], message);
+                for (line, isbn) in lines.into_iter().zip(isbns) {
+                    self.fail(line, Some(isbn), vec![message.clone()]);
+                }
+                self.error = Some(message);
+                return Ok(());
+            }
+        };
+        for ((line, isbn), result) in lines.into_iter().zip(isbns).zip(results) {
+            let row = match result {
+                Ok(book) => ImportRow {
+                    line,
+                    isbn: Some(isbn),
+                    status: if self.dry_run {
+                        RowStatus::Valid
+                    } else {
+                        RowStatus::Imported
+                    },
+                    book_id: (!self.dry_run).then_some(book.id),
+                    errors: Vec::new(),
+                },
+                Err(e) => ImportRow {
+                    line,
+                    isbn: Some(isbn),
+                    status: RowStatus::Failed,
+                    book_id: None,
+                    errors: vec![error_message(&e)],
+                },
+            };
+            self.record(row);
+        }
+        Ok(())
+    }
+
+    fn fail(&mut self, line: usize, isbn: Option<String>, errors: Vec<String>) {
+        self.record(ImportRow {
+            line,
+            isbn,
+            status: RowStatus::Failed,
+            book_id: None,
+            errors,
+        });
+    }
+
+    fn record(&mut self, row: ImportRow) {
+        self.total += 1;
+        if row.status == RowStatus::Failed {
+            self.failed += 1;
+        }
+        self.rows.push(row);
+    }
+}
+
+/// Imports the file at `path`, in `format` or else the one its extension stands for.
+pub async fn import_file(
+    service: Service,
+    actor: String,
+    path: &Path,
+    format: Option<ImportFormat>,
+    dry_run: bool,
+) -> Result<ImportReport, ImportError> {
+    let format = format
+        .or_else(|| ImportFormat::from_file_name(path.file_name()?.to_str()?))
+        .ok_or(ImportError::UnknownFormat)?;
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut importer = Importer::new(service, actor, format, dry_run);
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        importer.push(&buffer[..read]).await?;
+    }
+    importer.finish().await
+}
+
+fn parse_csv_record(record: &str) -> Result<Vec<String>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(record.as_bytes());
+    let mut fields = csv::StringRecord::new();
+    reader.read_record(&mut fields)?;
+    Ok(fields.iter().map(str::to_owned).collect())
+}
+
+/// The columns named by a CSV header, which must include `title` and `isbn`.
+fn csv_header(fields: &[String]) -> Result<Vec<String>, ImportError> {
+    let columns: Vec<String> = fields
+        .iter()
+        .map(|column| column.trim().to_ascii_lowercase())
+        .collect();
+    for (i, column) in columns.iter().enumerate() {
+        if !CSV_COLUMNS.contains(&column.as_str()) {
+            return Err(ImportError::InvalidHeader(format!(
+                "unknown column '{}'",
+                column
+            )));
+        }
+        if columns[..i].contains(column) {
+            return Err(ImportError::InvalidHeader(format!(
+                "column '{}' appears twice",
+                column
+            )));
+        }
+    }
+    for required in REQUIRED_COLUMNS {
+        if !columns.iter().any(|column| column == required) {
+            return Err(ImportError::InvalidHeader(format!(
+                "column '{}' is missing",
+                required
+            )));
+        }
+    }
+    Ok(columns)
+}
+
+/// The book in a CSV record. Empty cells are absent fields, and `author_ids` and
+/// `genre_ids` are lists of ids separated by `;`.
+fn csv_book(header: &[String], fields: &[String]) -> Result<NewBook, Vec<String>> {
+    if fields.len() != header.len() {
+        return Err(vec![format!(
+            "expected {} fields, found {}",
+            header.len(),
+            fields.len()
+        )]);
+    }
+    let mut book = Map::new();
+    let mut errors = Vec::new();
+    for (column, value) in header.iter().zip(fields) {
+        let value = value.trim();
+        if value.is_empty() && !REQUIRED_COLUMNS.contains(&column.as_str()) {
+            continue;
+        }
+        let value = match column.as_str() {
+            "author_ids" | "genre_ids" => value
+                .split(';')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(|id| id.parse::<i32>().map(Value::from))
+                .collect::<Result<Vec<Value>, _>>()
+                .map(Value::Array)
+                .map_err(|_| format!("{} must be integers separated by ';'", column)),
+            "publisher_id" | "page_count" => value
+                .parse::<i32>()
+                .map(Value::from)
+                .map_err(|_| format!("{} must be an integer", column)),
+            _ => Ok(Value::from(value)),
+        };
+        match value {
+            Ok(value) => {
+                book.insert(column.clone(), value);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    serde_json::from_value(Value::Object(book)).map_err(|e| vec![e.to_string()])
+}
+
+/// Every problem with the book on `line`, empty when it can be imported. The ISBNs
+/// of valid books go into `isbns`.
+fn validate(line: usize, book: &NewBook, isbns: &mut HashMap<String, usize>) -> Vec<String> {
+    let mut errors = Vec::new();
+    if book.title.trim().is_empty() {
+        errors.push("title is required".to_owned());
+    }
+    if book.isbn.trim().is_empty() {
+        errors.push("isbn is required".to_owned());
+    } else if !isbn::is_valid(&book.isbn) {
+        errors.push(format!(
+            "isbn '{}' is not an ISBN-10 or ISBN-13 with a valid check digit",
+            book.isbn
+        ));
+    }
+    errors.extend(book.metadata.validate());
+    if errors.is_empty() {
+        let canonical = isbn::canonical(&book.isbn);
+        if let Some(first) = isbns.get(&canonical) {
+            errors.push(format!("isbn '{}' is already on line {}", book.isbn, first));
+        } else {
+            isbns.insert(canonical, line);
+        }
+    }
+    errors
+}
+
+/// Why a row couldn't be created, as the service's own messages hide the
+/// repository's.
+fn error_message(error: &ServiceError) -> String {
+    match error {
+        ServiceError::RepositoryError(e) => e.to_string(),
+        e => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Vec<String> {
+        csv_header(&[
+            "Title".to_owned(),
+            "isbn".to_owned(),
+            "author_ids".to_owned(),
+        ])
+        .unwrap()
+    }
+
+    fn fields(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_csv_header() {
+        assert_eq!(fields(&["title", "isbn", "author_ids"]), header());
+        assert!(matches!(
+            csv_header(&fields(&["title", "author"])),
+            Err(ImportError::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            csv_header(&fields(&["title", "page_count"])),
+            Err(ImportError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_csv_book() {
+        let record = parse_csv_record("\"Rust, 2nd\",978-0-306-40615-7, 1; 2 ").unwrap();
+        let book = csv_book(&header(), &record).unwrap();
+        assert_eq!("Rust, 2nd", book.title);
+        assert_eq!("978-0-306-40615-7", book.isbn);
+        assert_eq!(vec![1, 2], book.references.author_ids);
+
+        assert_eq!(
+            Err(vec![
+                "author_ids must be integers separated by ';'".to_owned()
+            ]),
+            csv_book(&header(), &fields(&["Rust", "", "1;x"]))
+        );
+        assert!(csv_book(&header(), &fields(&["Rust", ""])).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut isbns = HashMap::new();
+        let record = parse_csv_record("Rust,0-306-40615-2,").unwrap();
+        let book = csv_book(&header(), &record).unwrap();
+        assert!(validate(2, &book, &mut isbns).is_empty());
+        assert_eq!(
+            vec!["isbn '0-306-40615-2' is already on line 2".to_owned()],
+            validate(3, &book, &mut isbns)
+        );
+        let isbn13 = csv_book(&header(), &fields(&["Rust", "9780306406157", ""])).unwrap();
+        assert_eq!(
+            vec!["isbn '9780306406157' is already on line 2".to_owned()],
+            validate(4, &isbn13, &mut isbns)
+        );
+
+        let book = csv_book(&header(), &fields(&[" ", "0-306-40615-3", ""])).unwrap();
+        assert_eq!(2, validate(5, &book, &mut isbns).len());
+    }
+
+    #[test]
+    fn test_report_csv() {
+        let rows = [
+            ImportRow {
+                line: 2,
+                isbn: Some("0-306-40615-2".to_owned()),
+                status: RowStatus::Imported,
+                book_id: Some(7),
+                errors: Vec::new(),
+            },
+            ImportRow {
+                line: 3,
+                isbn: None,
+                status: RowStatus::Failed,
+                book_id: None,
+                errors: vec![
+                    "title is required".to_owned(),
+                    "isbn is required".to_owned(),
+                ],
+            },
+        ];
+        assert_eq!(
+            "line,isbn,status,book_id,errors\n\
+             2,0-306-40615-2,imported,7,\n\
+             3,,failed,,title is required; isbn is required\n",
+            String::from_utf8(report_csv(&rows).unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_import_format_from_file_name() {
+        assert_eq!(
+            Some(ImportFormat::Csv),
+            ImportFormat::from_file_name("books.CSV")
+        );
+        assert_eq!(
+            Some(ImportFormat::Jsonl),
+            ImportFormat::from_file_name("books.jsonl")
+        );
+        assert_eq!(None, ImportFormat::from_file_name("books"));
+    }
+}
@@ -1,21 +1,66 @@
 pub mod dto;
 pub mod entity;
 pub mod http_servers;
+pub mod import;
 pub mod repository;
 pub mod service;
 
-use apache_avro::AvroSchema;
-use common::events::dto::CreatedBook;
+use std::{path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand};
+use common::events::{
+    compatibility::CompatibilityLevel,
+    topics::{self, BookCreated},
+};
 use database::get_connection;
 use http_servers::start_http_server;
-use kafka::utils::register_schema;
+use import::{import_file, report_csv, ImportFormat};
+use kafka::{admin::KafkaAdmin, compatibility::CompatibilityGate, utils::register_topic_schemas};
 use opentelemetry::global;
 use repository::Repository;
 use service::{book_created_producer::BookCreatedProducer, Service};
+use tracing::{error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
+/// Who purges appear to be made by in book histories.
+const PURGE_ACTOR: &str = "purge";
+
+/// Serves the books API and purges soft-deleted books, or imports books.
+#[derive(Parser)]
+struct Cli {
+    /// Days soft-deleted books stay restorable before they are purged.
+    #[arg(long, env = "BOOK_PURGE_RETENTION_DAYS", default_value_t = 30)]
+    purge_retention_days: i64,
+    /// Seconds between purges. Every instance tries; one at a time gets to purge.
+    #[arg(long, default_value_t = 3600)]
+    purge_interval_secs: u64,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Imports books from a CSV or JSON Lines file, as `POST /api/books/import` does.
+    Import {
+        file: PathBuf,
+        /// Taken from the file extension when absent.
+        #[arg(long)]
+        format: Option<ImportFormat>,
+        /// Validates every row against the database without creating anything.
+        #[arg(long)]
+        dry_run: bool,
+        /// Where to write the per-row report as CSV.
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Who the imported books' histories say created them.
+        #[arg(long, default_value = "import")]
+        actor: String,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
     opentelemetry::global::set_text_map_propagator(opentelemetry_zipkin::Propagator::new());
     let tracer = opentelemetry_zipkin::new_pipeline()
         .with_service_name("books_api".to_owned())
@@ -27,7 +72,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let subscriber = tracing_subscriber::fmt::layer().json();
 
-    let level = EnvFilter::new("debug".to_owned());
+    let level = EnvFilter::new("debug");
 
     tracing_subscriber::registry()
         .with(subscriber)
@@ -46,13 +91,66 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         BookCreatedProducer::new("localhost:9092".to_owned(), schema_registry_url.clone());
     let service = Service::new(repository, book_created_producer);
 
-    register_schema(
-        schema_registry_url,
-        "book".to_string(),
-        CreatedBook::get_schema(),
-    )
-    .await
-    .expect("Error while registering schema");
+    KafkaAdmin::new("localhost:9092".to_owned())
+        .provision(&topics::definitions(), false)
+        .await
+        .expect("Error while provisioning topics");
+
+    CompatibilityGate::new(schema_registry_url.clone(), CompatibilityLevel::Backward)
+        .check_topic::<BookCreated>()
+        .await
+        .expect("Incompatible schema change");
+
+    register_topic_schemas::<BookCreated>(schema_registry_url)
+        .await
+        .expect("Error while registering schema");
+
+    if let Some(Command::Import {
+        file,
+        format,
+        dry_run,
+        report,
+        actor,
+    }) = cli.command
+    {
+        let import_report = import_file(service.clone(), actor, &file, format, dry_run).await?;
+        let rows = service.import_report_rows(import_report.id).await?;
+        if let Some(report) = report {
+            tokio::fs::write(report, report_csv(&rows)?).await?;
+        } else {
+            for row in rows.iter().filter(|row| !row.errors.is_empty()) {
+                eprintln!("line {}: {}", row.line, row.errors.join("; "));
+            }
+        }
+        if let Some(error) = &import_report.error {
+            eprintln!("import stopped: {}", error);
+        }
+        println!(
+            "{} rows, {} {}, {} failed",
+            import_report.total,
+            import_report.succeeded,
+            if dry_run { "valid" } else { "imported" },
+            import_report.failed
+        );
+        global::shutdown_tracer_provider();
+        return Ok(());
+    }
+
+    let purge_service = service.clone();
+    let retention = chrono::Duration::days(cli.purge_retention_days);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(cli.purge_interval_secs));
+        loop {
+            interval.tick().await;
+            match purge_service
+                .purge_deleted_books(PURGE_ACTOR, retention)
+                .await
+            {
+                Ok(purged) => info!("Purged {} deleted books", purged),
+                Err(e) => error!("Error purging deleted books: {}", e),
+            }
+        }
+    });
 
     start_http_server(service).await;
     global::shutdown_tracer_provider();
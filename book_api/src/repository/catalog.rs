@@ -0,0 +1,375 @@
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, ModelTrait, QueryFilter,
+    QueryOrder, QuerySelect, TransactionTrait,
+};
+
+use super::{unique_violation, Repository, RepositoryError};
+use crate::dto::{
+    BookDetails, BookMetadata, BookOperation, BookReferences, CatalogEntry, CatalogKind,
+};
+use crate::entity::{author, book, book_author, book_genre, genre, prelude::*, publisher};
+
+impl From<author::Model> for CatalogEntry {
+    fn from(model: author::Model) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+        }
+    }
+}
+
+impl From<publisher::Model> for CatalogEntry {
+    fn from(model: publisher::Model) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+        }
+    }
+}
+
+impl From<genre::Model> for CatalogEntry {
+    fn from(model: genre::Model) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+        }
+    }
+}
+
+fn entries<M: Into<CatalogEntry>>(models: Vec<M>) -> Vec<CatalogEntry> {
+    models.into_iter().map(Into::into).collect()
+}
+
+impl Repository {
+    pub async fn list_catalog(
+        &self,
+        kind: CatalogKind,
+    ) -> Result<Vec<CatalogEntry>, RepositoryError> {
+        let conn = self.database_connection.as_ref();
+        Ok(match kind {
+            CatalogKind::Author => entries(
+                Author::find()
+                    .order_by_asc(author::Column::Name)
+                    .all(conn)
+                    .await?,
+            ),
+            CatalogKind::Publisher => entries(
+                Publisher::find()
+                    .order_by_asc(publisher::Column::Name)
+                    .all(conn)
+                    .await?,
+            ),
+            CatalogKind::Genre => entries(
+                Genre::find()
+                    .order_by_asc(genre::Column::Name)
+                    .all(conn)
+                    .await?,
+            ),
+        })
+    }
+
+    pub async fn get_catalog(
+        &self,
+        kind: CatalogKind,
+        id: i32,
+    ) -> Result<CatalogEntry, RepositoryError> {
+        let conn = self.database_connection.as_ref();
+        let entry = match kind {
+            CatalogKind::Author => Author::find_by_id(id).one(conn).await?.map(Into::into),
+            CatalogKind::Publisher => Publisher::find_by_id(id).one(conn).await?.map(Into::into),
+            CatalogKind::Genre => Genre::find_by_id(id).one(conn).await?.map(Into::into),
+        };
+        entry.ok_or_else(|| RepositoryError::NotFound(format!("{} {}", kind.name(), id)))
+    }
+
+    pub async fn create_catalog(
+        &self,
+        kind: CatalogKind,
+        name: String,
+    ) -> Result<CatalogEntry, RepositoryError> {
+        let conn = self.database_connection.as_ref();
+        self.check_name_free(kind, &name, None).await?;
+        let created = match kind {
+            CatalogKind::Author => author::ActiveModel {
+                name: Set(name.clone()),
+                ..Default::default()
+            }
+            .insert(conn)
+            .await
+            .map(Into::into),
+            CatalogKind::Publisher => publisher::ActiveModel {
+                name: Set(name.clone()),
+                ..Default::default()
+            }
+            .insert(conn)
+            .await
+            .map(Into::into),
+            CatalogKind::Genre => genre::ActiveModel {
+                name: Set(name.clone()),
+                ..Default::default()
+            }
+            .insert(conn)
+            .await
+            .map(Into::into),
+        };
+        match created {
+            Ok(entry) => Ok(entry),
+            Err(e) => Err(self.write_catalog_error(e, kind, &name, None).await),
+        }
+    }
+
+    pub async fn update_catalog(
+        &self,
+        kind: CatalogKind,
+        id: i32,
+        name: String,
+    ) -> Result<CatalogEntry, RepositoryError> {
+        let conn = self.database_connection.as_ref();
+        self.get_catalog(kind, id).await?;
+        self.check_name_free(kind, &name, Some(id)).await?;
+        let updated = match kind {
+            CatalogKind::Author => author::ActiveModel {
+                id: Set(id),
+                name: Set(name.clone()),
+            }
+            .update(conn)
+            .await
+            .map(Into::into),
+            CatalogKind::Publisher => publisher::ActiveModel {
+                id: Set(id),
+                name: Set(name.clone()),
+            }
+            .update(conn)
+            .await
+            .map(Into::into),
+            CatalogKind::Genre => genre::ActiveModel {
+                id: Set(id),
+                name: Set(name.clone()),
+            }
+            .update(conn)
+            .await
+            .map(Into::into),
+        };
+        match updated {
+            Ok(entry) => Ok(entry),
+            Err(e) => Err(self.write_catalog_error(e, kind, &name, Some(id)).await),
+        }
+    }
+
+    /// Deletes an entry on behalf of `actor`. Books lose their links to a deleted
+    /// author or genre, and their publisher when it is the one deleted, which counts
+    /// as an update of each: it gets a new version and a history entry. Returns those
+    /// books, with whether each is soft-deleted.
+    pub async fn delete_catalog(
+        &self,
+        actor: &str,
+        kind: CatalogKind,
+        id: i32,
+    ) -> Result<Vec<(BookDetails, bool)>, RepositoryError> {
+        let txn = self.database_connection.begin().await?;
+        // Locking the entry keeps new links to it out until it is gone.
+        let found = match kind {
+            CatalogKind::Author => Author::find_by_id(id)
+                .lock_exclusive()
+                .one(&txn)
+                .await?
+                .is_some(),
+            CatalogKind::Publisher => Publisher::find_by_id(id)
+                .lock_exclusive()
+                .one(&txn)
+                .await?
+                .is_some(),
+            CatalogKind::Genre => Genre::find_by_id(id)
+                .lock_exclusive()
+                .one(&txn)
+                .await?
+                .is_some(),
+        };
+        if !found {
+            return Err(RepositoryError::NotFound(format!("{} {}", kind.name(), id)));
+        }
+        let linked = match kind {
+            CatalogKind::Author => Book::find()
+                .inner_join(BookAuthor)
+                .filter(book_author::Column::AuthorId.eq(id)),
+            CatalogKind::Publisher => Book::find().filter(book::Column::PublisherId.eq(id)),
+            CatalogKind::Genre => Book::find()
+                .inner_join(BookGenre)
+                .filter(book_genre::Column::GenreId.eq(id)),
+        }
+        .order_by_asc(book::Column::Id)
+        .lock_exclusive()
+        .all(&txn)
+        .await?;
+        let mut befores = Vec::with_capacity(linked.len());
+        for book in &linked {
+            befores.push(Self::load_book_record(&txn, book).await?);
+        }
+        match kind {
+            CatalogKind::Author => Author::delete_by_id(id).exec(&txn).await?,
+            CatalogKind::Publisher => Publisher::delete_by_id(id).exec(&txn).await?,
+            CatalogKind::Genre => Genre::delete_by_id(id).exec(&txn).await?,
+        };
+        let mut updated = Vec::with_capacity(linked.len());
+        for (book, before) in linked.into_iter().zip(befores) {
+            let book = book::ActiveModel {
+                id: Set(book.id),
+                version: Set(book.version + 1),
+                ..Default::default()
+            }
+            .update(&txn)
+            .await?;
+            let after = Self::load_book_record(&txn, &book).await?;
+            Self::record_history(
+                &txn,
+                actor,
+                BookOperation::Update,
+                book.id,
+                book.version,
+                Some(&before),
+                Some(&after),
+            )
+            .await?;
+            let deleted = book.deleted_at.is_some();
+            updated.push((Self::book_details(&txn, book).await?, deleted));
+        }
+        txn.commit().await?;
+        Ok(updated)
+    }
+
+    /// Publishers and genres are unique by name; authors may share one.
+    async fn check_name_free(
+        &self,
+        kind: CatalogKind,
+        name: &str,
+        except: Option<i32>,
+    ) -> Result<(), RepositoryError> {
+        let conn = self.database_connection.as_ref();
+        let existing = match kind {
+            CatalogKind::Author => None,
+            CatalogKind::Publisher => Publisher::find()
+                .filter(publisher::Column::Name.eq(name))
+                .one(conn)
+                .await?
+                .map(|model| model.id),
+            CatalogKind::Genre => Genre::find()
+                .filter(genre::Column::Name.eq(name))
+                .one(conn)
+                .await?
+                .map(|model| model.id),
+        };
+        match existing {
+            Some(id) if Some(id) != except => Err(RepositoryError::Conflict(format!(
+                "{} {} named '{}'",
+                kind.name(),
+                id,
+                name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// `e` from writing an entry named `name`, as a conflict when it lost a race with a
+    /// concurrent write of the same name.
+    async fn write_catalog_error(
+        &self,
+        e: DbErr,
+        kind: CatalogKind,
+        name: &str,
+        except: Option<i32>,
+    ) -> RepositoryError {
+        if unique_violation(&e).is_none() {
+            return e.into();
+        }
+        match self.check_name_free(kind, name, except).await {
+            Ok(()) => RepositoryError::Conflict(format!("{} named '{}'", kind.name(), name)),
+            Err(conflict) => conflict,
+        }
+    }
+
+    /// Fails on the first reference to an entry that doesn't exist.
+    pub(crate) async fn check_references<C: ConnectionTrait>(
+        conn: &C,
+        references: &BookReferences,
+    ) -> Result<(), RepositoryError> {
+        if let Some(id) = references.publisher_id {
+            if Publisher::find_by_id(id).one(conn).await?.is_none() {
+                return Err(RepositoryError::InvalidReference(format!(
+                    "publisher {}",
+                    id
+                )));
+            }
+        }
+        let authors: Vec<i32> = Author::find()
+            .filter(author::Column::Id.is_in(references.author_ids.clone()))
+            .all(conn)
+            .await?
+            .into_iter()
+            .map(|model| model.id)
+            .collect();
+        if let Some(id) = references
+            .author_ids
+            .iter()
+            .find(|id| !authors.contains(id))
+        {
+            return Err(RepositoryError::InvalidReference(format!("author {}", id)));
+        }
+        let genres: Vec<i32> = Genre::find()
+            .filter(genre::Column::Id.is_in(references.genre_ids.clone()))
+            .all(conn)
+            .await?
+            .into_iter()
+            .map(|model| model.id)
+            .collect();
+        if let Some(id) = references.genre_ids.iter().find(|id| !genres.contains(id)) {
+            return Err(RepositoryError::InvalidReference(format!("genre {}", id)));
+        }
+        Ok(())
+    }
+
+    pub async fn get_book(&self, id: i32) -> Result<BookDetails, RepositoryError> {
+        let conn = self.database_connection.as_ref();
+        let book = Self::live_books()
+            .filter(book::Column::Id.eq(id))
+            .one(conn)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("book {}", id)))?;
+        Self::book_details(conn, book).await
+    }
+
+    /// `book` with its publisher, authors and genres.
+    pub(crate) async fn book_details<C: ConnectionTrait>(
+        conn: &C,
+        book: book::Model,
+    ) -> Result<BookDetails, RepositoryError> {
+        let publisher = book.find_related(Publisher).one(conn).await?;
+        let authors = book
+            .find_related(Author)
+            .order_by_asc(author::Column::Name)
+            .all(conn)
+            .await?;
+        let genres = book
+            .find_related(Genre)
+            .order_by_asc(genre::Column::Name)
+            .all(conn)
+            .await?;
+        Ok(BookDetails {
+            id: book.id,
+            title: book.title,
+            isbn: book.isbn,
+            publisher: publisher.map(Into::into),
+            authors: entries(authors),
+            genres: entries(genres),
+            metadata: BookMetadata {
+                published_on: book.published_on,
+                language: book.language,
+                page_count: book.page_count,
+                edition: book.edition,
+                description: book.description,
+                cover_url: book.cover_url,
+            },
+            version: book.version,
+        })
+    }
+}
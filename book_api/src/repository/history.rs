@@ -0,0 +1,222 @@
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder,
+};
+use serde_json::{json, Map, Value};
+
+use super::{Repository, RepositoryError};
+use crate::dto::{
+    BookAsOf, BookHistoryEntry, BookMetadata, BookOperation, BookRecord, BookReferences,
+};
+use crate::entity::book::Model as BookModel;
+use crate::entity::{book_author, book_genre, book_history, prelude::*};
+
+impl From<book_history::Model> for BookHistoryEntry {
+    fn from(model: book_history::Model) -> Self {
+        Self {
+            version: model.version,
+            operation: model.operation,
+            actor: model.actor,
+            changed_at: model.changed_at,
+            diff: model.diff,
+        }
+    }
+}
+
+/// The tracked fields of `book`, linked to the catalog entries of `references`.
+pub(crate) fn book_record(book: &BookModel, references: BookReferences) -> BookRecord {
+    BookRecord {
+        title: book.title.clone(),
+        isbn: book.isbn.clone(),
+        references,
+        metadata: BookMetadata {
+            published_on: book.published_on,
+            language: book.language.clone(),
+            page_count: book.page_count,
+            edition: book.edition.clone(),
+            description: book.description.clone(),
+            cover_url: book.cover_url.clone(),
+        },
+        deleted_at: book.deleted_at,
+    }
+}
+
+/// The fields that differ between two JSON objects, as `{"field": {"from": .., "to": ..}}`.
+/// Missing fields count as null.
+fn diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+    let changes: Map<String, Value> = fields
+        .into_iter()
+        .filter_map(|field| {
+            let from = before.get(field).unwrap_or(&Value::Null);
+            let to = after.get(field).unwrap_or(&Value::Null);
+            (from != to).then(|| (field.clone(), json!({ "from": from, "to": to })))
+        })
+        .collect();
+    Value::Object(changes)
+}
+
+/// The object that applying the `to` side of each of `diffs` in turn leads to,
+/// starting from nothing.
+fn replay<'a>(diffs: impl IntoIterator<Item = &'a Value>) -> Map<String, Value> {
+    let mut state = Map::new();
+    for changes in diffs.into_iter().filter_map(Value::as_object) {
+        for (field, change) in changes {
+            match change.get("to") {
+                Some(Value::Null) | None => state.remove(field),
+                Some(to) => state.insert(field.clone(), to.clone()),
+            };
+        }
+    }
+    state
+}
+
+impl Repository {
+    /// The tracked fields of `book` as they are in `db`.
+    pub(crate) async fn load_book_record<C: ConnectionTrait>(
+        db: &C,
+        book: &BookModel,
+    ) -> Result<BookRecord, RepositoryError> {
+        let author_ids = BookAuthor::find()
+            .filter(book_author::Column::BookId.eq(book.id))
+            .order_by_asc(book_author::Column::AuthorId)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|link| link.author_id)
+            .collect();
+        let genre_ids = BookGenre::find()
+            .filter(book_genre::Column::BookId.eq(book.id))
+            .order_by_asc(book_genre::Column::GenreId)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|link| link.genre_id)
+            .collect();
+        let references = BookReferences {
+            publisher_id: book.publisher_id,
+            author_ids,
+            genre_ids,
+        };
+        Ok(book_record(book, references))
+    }
+
+    /// Records a change of book `id` by `actor`, from `before` to `after`, where `None`
+    /// is a book that doesn't exist. Meant to run in the transaction of the change.
+    pub(crate) async fn record_history<C: ConnectionTrait>(
+        db: &C,
+        actor: &str,
+        operation: BookOperation,
+        id: i32,
+        version: i32,
+        before: Option<&BookRecord>,
+        after: Option<&BookRecord>,
+    ) -> Result<(), RepositoryError> {
+        let to_value = |record: Option<&BookRecord>| -> Result<Value, RepositoryError> {
+            Ok(match record {
+                Some(record) => serde_json::to_value(record)?,
+                None => Value::Null,
+            })
+        };
+        book_history::ActiveModel {
+            book_id: Set(id),
+            version: Set(version),
+            operation: Set(operation),
+            actor: Set(actor.to_owned()),
+            diff: Set(diff(&to_value(before)?, &to_value(after)?)),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(())
+    }
+
+    /// Every change of book `id`, oldest version first, including those of a purged
+    /// book. Baselines added for books older than their history sort first.
+    pub async fn book_history(&self, id: i32) -> Result<Vec<BookHistoryEntry>, RepositoryError> {
+        let entries = BookHistory::find()
+            .filter(book_history::Column::BookId.eq(id))
+            .order_by_asc(book_history::Column::Version)
+            .order_by_asc(book_history::Column::Id)
+            .all(self.database_connection.as_ref())
+            .await?;
+        if entries.is_empty() {
+            return Err(RepositoryError::NotFound(format!("history of book {}", id)));
+        }
+        Ok(entries.into_iter().map(Into::into).collect())
+    }
+
+    /// Book `id` as it was at `as_of`, replaying its history up to then. Soft-deleted
+    /// books are returned with their `deleted_at`; books not created yet or purged
+    /// by then aren't found.
+    pub async fn book_as_of(
+        &self,
+        id: i32,
+        as_of: DateTimeWithTimeZone,
+    ) -> Result<BookAsOf, RepositoryError> {
+        let entries = BookHistory::find()
+            .filter(book_history::Column::BookId.eq(id))
+            .filter(book_history::Column::ChangedAt.lte(as_of))
+            .order_by_asc(book_history::Column::Version)
+            .order_by_asc(book_history::Column::Id)
+            .all(self.database_connection.as_ref())
+            .await?;
+        let not_found = || RepositoryError::NotFound(format!("book {} as of {}", id, as_of));
+        let last = entries.last().ok_or_else(not_found)?;
+        if last.operation == BookOperation::Purge {
+            return Err(not_found());
+        }
+        let version = last.version;
+        let state = replay(entries.iter().map(|entry| &entry.diff));
+        Ok(BookAsOf {
+            id,
+            version,
+            as_of,
+            record: serde_json::from_value(Value::Object(state))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{diff, replay};
+
+    #[test]
+    fn test_diff_and_replay() {
+        let created = diff(
+            &json!(null),
+            &json!({ "title": "Rust", "author_ids": [1], "language": "en" }),
+        );
+        assert_eq!(
+            json!({
+                "author_ids": { "from": null, "to": [1] },
+                "language": { "from": null, "to": "en" },
+                "title": { "from": null, "to": "Rust" },
+            }),
+            created
+        );
+        let updated = diff(
+            &json!({ "title": "Rust", "author_ids": [1], "language": "en" }),
+            &json!({ "title": "Rust, 2nd", "author_ids": [1], "language": null }),
+        );
+        assert_eq!(
+            json!({
+                "language": { "from": "en", "to": null },
+                "title": { "from": "Rust", "to": "Rust, 2nd" },
+            }),
+            updated
+        );
+        assert_eq!(
+            json!({ "title": "Rust, 2nd", "author_ids": [1] }),
+            json!(replay([&created, &updated]))
+        );
+    }
+}
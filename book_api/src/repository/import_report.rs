@@ -0,0 +1,135 @@
+use chrono::Utc;
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use super::{Repository, RepositoryError};
+use crate::entity::{import_report, import_report_row};
+use crate::import::{ImportReport, ImportRow};
+
+impl From<import_report::Model> for ImportReport {
+    fn from(model: import_report::Model) -> Self {
+        Self {
+            id: model.id,
+            dry_run: model.dry_run,
+            total: model.total as usize,
+            succeeded: model.succeeded as usize,
+            failed: model.failed as usize,
+            error: model.error,
+            started_at: model.started_at,
+            finished_at: model.finished_at,
+        }
+    }
+}
+
+impl TryFrom<import_report_row::Model> for ImportRow {
+    type Error = RepositoryError;
+
+    fn try_from(model: import_report_row::Model) -> Result<Self, Self::Error> {
+        Ok(Self {
+            line: model.line as usize,
+            isbn: model.isbn,
+            status: model.status,
+            book_id: model.book_id,
+            errors: serde_json::from_value(model.errors)?,
+        })
+    }
+}
+
+impl Repository {
+    /// Starts the report of an import by `actor`, dropping the reports started
+    /// before `expired_before`.
+    pub async fn create_import_report(
+        &self,
+        actor: &str,
+        dry_run: bool,
+        expired_before: DateTimeWithTimeZone,
+    ) -> Result<ImportReport, RepositoryError> {
+        let conn = self.database_connection.as_ref();
+        // Their rows go with them.
+        import_report::Entity::delete_many()
+            .filter(import_report::Column::StartedAt.lt(expired_before))
+            .exec(conn)
+            .await?;
+        let report = import_report::ActiveModel {
+            actor: Set(actor.to_owned()),
+            dry_run: Set(dry_run),
+            ..Default::default()
+        }
+        .insert(conn)
+        .await?;
+        Ok(report.into())
+    }
+
+    /// Adds `rows` to report `report_id`.
+    pub async fn add_import_rows(
+        &self,
+        report_id: i32,
+        rows: &[ImportRow],
+    ) -> Result<(), RepositoryError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let rows = rows
+            .iter()
+            .map(|row| {
+                Ok(import_report_row::ActiveModel {
+                    report_id: Set(report_id),
+                    line: Set(row.line as i32),
+                    isbn: Set(row.isbn.clone()),
+                    status: Set(row.status),
+                    book_id: Set(row.book_id),
+                    errors: Set(serde_json::to_value(&row.errors)?),
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, RepositoryError>>()?;
+        import_report_row::Entity::insert_many(rows)
+            .exec(self.database_connection.as_ref())
+            .await?;
+        Ok(())
+    }
+
+    /// Records the totals and error of `report`, and that it is finished.
+    pub async fn finish_import_report(
+        &self,
+        report: &ImportReport,
+    ) -> Result<ImportReport, RepositoryError> {
+        let report = import_report::ActiveModel {
+            id: Set(report.id),
+            total: Set(report.total as i32),
+            succeeded: Set(report.succeeded as i32),
+            failed: Set(report.failed as i32),
+            error: Set(report.error.clone()),
+            finished_at: Set(Some(Utc::now().into())),
+            ..Default::default()
+        }
+        .update(self.database_connection.as_ref())
+        .await?;
+        Ok(report.into())
+    }
+
+    pub async fn import_report(&self, id: i32) -> Result<ImportReport, RepositoryError> {
+        let report = import_report::Entity::find_by_id(id)
+            .one(self.database_connection.as_ref())
+            .await?;
+        report
+            .map(Into::into)
+            .ok_or_else(|| RepositoryError::NotFound(format!("import report {}", id)))
+    }
+
+    /// The rows of report `report_id`, in the order of the file.
+    pub async fn import_report_rows(
+        &self,
+        report_id: i32,
+    ) -> Result<Vec<ImportRow>, RepositoryError> {
+        import_report_row::Entity::find()
+            .filter(import_report_row::Column::ReportId.eq(report_id))
+            .order_by_asc(import_report_row::Column::Line)
+            .all(self.database_connection.as_ref())
+            .await?
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect()
+    }
+}
@@ -1,9 +1,19 @@
+use chrono::NaiveDate;
 use common::events::{
-    constants::Topics,
-    dto::{CreatedBookBuilder, CreatedBookBuilderError},
+    dto::{CreatedBookBuilder, CreatedBookBuilderError, Date},
+    topics::BookCreated,
 };
+use futures::future::join_all;
 use kafka::producer::KafkaProducer;
+
+use crate::dto::Book;
 use thiserror::Error;
+use tracing::error;
+
+/// `date` as the days since the Unix epoch, which `NaiveDate::default()` is.
+fn event_date(date: NaiveDate) -> Date {
+    Date::from_days_since_epoch((date - NaiveDate::default()).num_days() as i32)
+}
 
 #[derive(Clone)]
 pub struct BookCreatedProducer {
@@ -23,25 +33,69 @@ impl BookCreatedProducer {
         }
     }
 
+    /// Publishes the state of `book`, a deleted one when it was soft-deleted.
     pub async fn publish_created_book(
         &self,
-        id: i32,
-        title: String,
-        isbn: String,
+        book: Book,
+        deleted: bool,
     ) -> Result<bool, BookCreatedProducerError> {
+        let id = book.id;
+        let metadata = book.metadata;
         let created_book = CreatedBookBuilder::default()
             .id(id)
-            .title(title)
-            .isbn(isbn)
+            .title(book.title)
+            .isbn(book.isbn)
+            .publisher_id(book.publisher_id)
+            .author_ids(book.author_ids)
+            .genre_ids(book.genre_ids)
+            .published_on(metadata.published_on.map(event_date))
+            .language(metadata.language)
+            .page_count(metadata.page_count)
+            .edition(metadata.edition)
+            .description(metadata.description)
+            .cover_url(metadata.cover_url)
+            .version(book.version)
+            .deleted(deleted)
             .build()
-            .map_err(|e| BookCreatedProducerError::CreatedBookBuilderError(e))?;
-        Ok(self
-            .producer
-            .produce(
-                id.to_string(),
-                created_book,
-                Topics::BookCreated.to_string(),
-            )
-            .await)
+            .map_err(BookCreatedProducerError::CreatedBookBuilderError)?;
+        Ok(self.producer.publish::<BookCreated>(id, created_book).await)
+    }
+
+    /// Publishes the states of `books` concurrently and returns how many were
+    /// delivered. Failures are logged per book.
+    pub async fn publish_created_books(&self, books: Vec<Book>) -> usize {
+        let deliveries = books.into_iter().map(|book| async move {
+            let id = book.id;
+            match self.publish_created_book(book, false).await {
+                Ok(delivered) => delivered,
+                Err(e) => {
+                    error!("Error publishing book {}: {}", id, e);
+                    false
+                }
+            }
+        });
+        join_all(deliveries)
+            .await
+            .into_iter()
+            .filter(|delivered| *delivered)
+            .count()
+    }
+
+    /// Publishes a tombstone for purged book `id`, so that compaction drops it.
+    pub async fn publish_tombstone(&self, id: i32) -> bool {
+        self.producer.publish_tombstone::<BookCreated>(id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_date() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(0, event_date(date(1970, 1, 1)).days_since_epoch());
+        assert_eq!(19_875, event_date(date(2024, 6, 1)).days_since_epoch());
+        assert_eq!(-1, event_date(date(1969, 12, 31)).days_since_epoch());
     }
 }
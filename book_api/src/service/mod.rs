@@ -1,11 +1,19 @@
 use std::sync::Arc;
 
 use book_created_producer::{BookCreatedProducer, BookCreatedProducerError};
+use chrono::{Duration, Utc};
+use sea_orm::prelude::DateTimeWithTimeZone;
 use thiserror::Error;
-use tracing::{info_span, Instrument};
+use tracing::{error, info, info_span, Instrument};
 
 use crate::{
-    dto::{Book, BookBuilder, BookBuilderError},
+    dto::{
+        Book, BookAsOf, BookBuilder, BookBuilderError, BookDetails, BookHistoryEntry, BookMetadata,
+        BookReferences, BookSearchHit, BookSummary, CatalogEntry, CatalogKind, DeletedFilter,
+        DuplicateIsbn, NewBook,
+    },
+    entity::book::Model as BookModel,
+    import::{ImportReport, ImportRow},
     repository::{Repository, RepositoryError},
 };
 
@@ -27,6 +35,9 @@ pub enum ServiceError {
 
     #[error("BookCreatedProducerError error")]
     BookCreatedProducerError(#[from] BookCreatedProducerError),
+
+    #[error("Invalid book: {}", .0.join(", "))]
+    ValidationError(Vec<String>),
 }
 
 impl Service {
@@ -37,39 +48,329 @@ impl Service {
         }
     }
 
-    pub async fn create_book(&self, title: String, isbn: String) -> Result<Book, ServiceError> {
+    /// Creates a book on behalf of `actor`, whom the book's history records like
+    /// for every other write.
+    pub async fn create_book(
+        &self,
+        actor: &str,
+        title: String,
+        isbn: String,
+        mut references: BookReferences,
+        metadata: BookMetadata,
+    ) -> Result<Book, ServiceError> {
+        let errors = metadata.validate();
+        if !errors.is_empty() {
+            return Err(ServiceError::ValidationError(errors));
+        }
         let span = info_span!("create and publish book");
+        dedup_references(&mut references);
 
-        let created_book_model = async move {
+        let created_book_model = async {
             let m = self
                 .repo
-                .create_book(title, isbn)
+                .create_book(actor, title, isbn, &references, &metadata)
                 .await
-                .map_err(|e| ServiceError::RepositoryError(e));
+                .map_err(ServiceError::RepositoryError);
             m
         }
         .instrument(span)
         .await?;
 
-        let producer = self.book_created_producer.clone();
-        let created_book_id = created_book_model.clone().id;
-        let created_book_title = created_book_model.clone().title;
-        let created_book_isbn = created_book_model.clone().isbn;
-        let _ = tokio::task::spawn(async move {
-            let _ = producer.publish_created_book(
-                created_book_id,
-                created_book_title,
-                created_book_isbn,
-            );
-        })
-        .instrument(info_span!("publish_created_book"));
-
-        let book = BookBuilder::default()
-            .id(created_book_model.id)
-            .title(created_book_model.title)
-            .isbn(created_book_model.isbn)
-            .build()
-            .map_err(|e| ServiceError::BookBuilderError(e))?;
+        self.published(created_book_model, references, metadata)
+            .await
+    }
+
+    /// Replaces book `id` if its version is one of `expected_versions`, or
+    /// unconditionally when they are `None`.
+    pub async fn update_book(
+        &self,
+        actor: &str,
+        id: i32,
+        expected_versions: Option<&[i32]>,
+        mut book: NewBook,
+    ) -> Result<Book, ServiceError> {
+        let errors = book.metadata.validate();
+        if !errors.is_empty() {
+            return Err(ServiceError::ValidationError(errors));
+        }
+        dedup_references(&mut book.references);
+        let updated_book_model = self
+            .repo
+            .update_book(actor, id, expected_versions, &book)
+            .instrument(info_span!("update book"))
+            .await?;
+        self.published(updated_book_model, book.references, book.metadata)
+            .await
+    }
+
+    /// Soft-deletes book `id`, with the same version check as updates.
+    pub async fn delete_book(
+        &self,
+        actor: &str,
+        id: i32,
+        expected_versions: Option<&[i32]>,
+    ) -> Result<(), ServiceError> {
+        let deleted = self.repo.delete_book(actor, id, expected_versions).await?;
+        self.publish(deleted.into(), true).await
+    }
+
+    pub async fn restore_book(
+        &self,
+        actor: &str,
+        id: i32,
+        expected_versions: Option<&[i32]>,
+    ) -> Result<Book, ServiceError> {
+        let book: Book = self
+            .repo
+            .restore_book(actor, id, expected_versions)
+            .await?
+            .into();
+        self.publish(book.clone(), false).await?;
         Ok(book)
     }
+
+    pub async fn list_books(
+        &self,
+        deleted: DeletedFilter,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Vec<BookSummary>, ServiceError> {
+        Ok(self.repo.list_books(deleted, limit, offset).await?)
+    }
+
+    /// Deletes the books soft-deleted more than `retention` ago, after publishing a
+    /// tombstone for each. Returns how many were purged, none when another instance
+    /// is already purging.
+    pub async fn purge_deleted_books(
+        &self,
+        actor: &str,
+        retention: Duration,
+    ) -> Result<usize, ServiceError> {
+        let Some(lock) = self.repo.lock_purge().await? else {
+            info!("Skipping purge, another instance is purging");
+            return Ok(0);
+        };
+        let cutoff = (Utc::now() - retention).into();
+        let mut tombstoned = Vec::new();
+        for id in self.repo.purgeable_books(cutoff).await? {
+            // A book is only purged once its tombstone is out, or consumers would keep it.
+            if self.book_created_producer.publish_tombstone(id).await {
+                tombstoned.push(id);
+            } else {
+                error!("Error publishing the tombstone of book {}", id);
+            }
+        }
+        let purged = self.repo.purge_books(actor, &tombstoned, cutoff).await?;
+        // Books restored after their tombstone went out must be published again.
+        for id in tombstoned.into_iter().filter(|id| !purged.contains(id)) {
+            let republished = match self.repo.get_book(id).await {
+                Ok(book) => self.publish(book.into(), false).await,
+                Err(RepositoryError::NotFound(_)) => Ok(()),
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = republished {
+                error!("Error republishing restored book {}: {}", id, e);
+            }
+        }
+        lock.release().await?;
+        Ok(purged.len())
+    }
+
+    /// Creates `books`, which must have been validated, in one batch and publishes
+    /// the created ones together. A `dry_run` creates and publishes nothing, but
+    /// still reports what would have failed.
+    pub async fn import_books(
+        &self,
+        actor: &str,
+        mut books: Vec<NewBook>,
+        dry_run: bool,
+    ) -> Result<Vec<Result<Book, ServiceError>>, ServiceError> {
+        for book in &mut books {
+            dedup_references(&mut book.references);
+        }
+        let results = self
+            .repo
+            .create_books(actor, &books, dry_run)
+            .instrument(info_span!("import books", count = books.len()))
+            .await?;
+        let results: Vec<Result<Book, ServiceError>> = results
+            .into_iter()
+            .zip(books)
+            .map(|(result, book)| build_book(result?, book.references, book.metadata))
+            .collect();
+        if !dry_run {
+            let created = results.iter().filter_map(|r| r.as_ref().ok()).cloned();
+            let published = self
+                .book_created_producer
+                .publish_created_books(created.collect())
+                .instrument(info_span!("publish_created_books"))
+                .await;
+            let failed = results.iter().filter(|r| r.is_ok()).count() - published;
+            if failed > 0 {
+                error!("Error publishing {} imported books", failed);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Starts the report of an import by `actor`, dropping the reports started
+    /// before `expired_before`.
+    pub async fn create_import_report(
+        &self,
+        actor: &str,
+        dry_run: bool,
+        expired_before: DateTimeWithTimeZone,
+    ) -> Result<ImportReport, ServiceError> {
+        Ok(self
+            .repo
+            .create_import_report(actor, dry_run, expired_before)
+            .await?)
+    }
+
+    pub async fn add_import_rows(
+        &self,
+        report_id: i32,
+        rows: &[ImportRow],
+    ) -> Result<(), ServiceError> {
+        Ok(self.repo.add_import_rows(report_id, rows).await?)
+    }
+
+    pub async fn finish_import_report(
+        &self,
+        report: &ImportReport,
+    ) -> Result<ImportReport, ServiceError> {
+        Ok(self.repo.finish_import_report(report).await?)
+    }
+
+    pub async fn import_report(&self, id: i32) -> Result<ImportReport, ServiceError> {
+        Ok(self.repo.import_report(id).await?)
+    }
+
+    pub async fn import_report_rows(&self, id: i32) -> Result<Vec<ImportRow>, ServiceError> {
+        Ok(self.repo.import_report_rows(id).await?)
+    }
+
+    /// Builds a book just written and publishes it.
+    async fn published(
+        &self,
+        model: BookModel,
+        references: BookReferences,
+        metadata: BookMetadata,
+    ) -> Result<Book, ServiceError> {
+        let book = build_book(model, references, metadata)?;
+        self.publish(book.clone(), false).await?;
+        Ok(book)
+    }
+
+    /// Publishes the state of `book` before the change is acknowledged. The topic is
+    /// compacted by book id, so publishing in the background could let an older
+    /// version land last and be the one kept.
+    async fn publish(&self, book: Book, deleted: bool) -> Result<(), ServiceError> {
+        let id = book.id;
+        if !self
+            .book_created_producer
+            .publish_created_book(book, deleted)
+            .instrument(info_span!("publish_created_book"))
+            .await?
+        {
+            error!("Error publishing book {}: not delivered", id);
+        }
+        Ok(())
+    }
+
+    pub async fn get_book(&self, id: i32) -> Result<BookDetails, ServiceError> {
+        Ok(self.repo.get_book(id).await?)
+    }
+
+    pub async fn book_history(&self, id: i32) -> Result<Vec<BookHistoryEntry>, ServiceError> {
+        Ok(self.repo.book_history(id).await?)
+    }
+
+    pub async fn book_as_of(
+        &self,
+        id: i32,
+        as_of: DateTimeWithTimeZone,
+    ) -> Result<BookAsOf, ServiceError> {
+        Ok(self.repo.book_as_of(id, as_of).await?)
+    }
+
+    pub async fn list_catalog(&self, kind: CatalogKind) -> Result<Vec<CatalogEntry>, ServiceError> {
+        Ok(self.repo.list_catalog(kind).await?)
+    }
+
+    pub async fn get_catalog(
+        &self,
+        kind: CatalogKind,
+        id: i32,
+    ) -> Result<CatalogEntry, ServiceError> {
+        Ok(self.repo.get_catalog(kind, id).await?)
+    }
+
+    pub async fn create_catalog(
+        &self,
+        kind: CatalogKind,
+        name: String,
+    ) -> Result<CatalogEntry, ServiceError> {
+        Ok(self.repo.create_catalog(kind, name).await?)
+    }
+
+    pub async fn update_catalog(
+        &self,
+        kind: CatalogKind,
+        id: i32,
+        name: String,
+    ) -> Result<CatalogEntry, ServiceError> {
+        Ok(self.repo.update_catalog(kind, id, name).await?)
+    }
+
+    /// Deletes a catalog entry on behalf of `actor` and publishes the books that
+    /// lost their link to it.
+    pub async fn delete_catalog(
+        &self,
+        actor: &str,
+        kind: CatalogKind,
+        id: i32,
+    ) -> Result<(), ServiceError> {
+        for (book, deleted) in self.repo.delete_catalog(actor, kind, id).await? {
+            self.publish(book.into(), deleted).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn search_books(
+        &self,
+        query: &str,
+        limit: u64,
+    ) -> Result<Vec<BookSearchHit>, ServiceError> {
+        Ok(self.repo.search_books(query, limit).await?)
+    }
+
+    pub async fn duplicate_isbns(&self) -> Result<Vec<DuplicateIsbn>, ServiceError> {
+        Ok(self.repo.duplicate_isbns().await?)
+    }
+}
+
+fn build_book(
+    model: BookModel,
+    references: BookReferences,
+    metadata: BookMetadata,
+) -> Result<Book, ServiceError> {
+    BookBuilder::default()
+        .id(model.id)
+        .title(model.title)
+        .isbn(model.isbn)
+        .publisher_id(references.publisher_id)
+        .author_ids(references.author_ids)
+        .genre_ids(references.genre_ids)
+        .metadata(metadata)
+        .version(model.version)
+        .build()
+        .map_err(ServiceError::BookBuilderError)
+}
+
+fn dedup_references(references: &mut BookReferences) {
+    for ids in [&mut references.author_ids, &mut references.genre_ids] {
+        ids.sort_unstable();
+        ids.dedup();
+    }
 }
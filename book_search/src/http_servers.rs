@@ -0,0 +1,62 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use axum_tracing_opentelemetry::opentelemetry_tracing_layer;
+use book_search::index::{BookIndex, IndexError, SearchRequest};
+use serde_json::json;
+
+pub async fn start_http_server(index: Arc<BookIndex>) {
+    let search_router = Router::new()
+        .route("/", get(search))
+        .route("/status", get(status));
+    let api_router = Router::new().nest("/search", search_router);
+    let app = Router::new()
+        .nest("/api", api_router)
+        .layer(opentelemetry_tracing_layer())
+        .layer(Extension(index));
+    let addr = SocketAddr::from(([127, 0, 0, 1], 8092));
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+        .unwrap()
+}
+
+async fn search(
+    Query(request): Query<SearchRequest>,
+    Extension(index): Extension<Arc<BookIndex>>,
+) -> Response {
+    match index.search(&request) {
+        Ok(results) => Json(results).into_response(),
+        Err(e @ IndexError::QueryError(_)) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+async fn status(Extension(index): Extension<Arc<BookIndex>>) -> Response {
+    match index.offsets() {
+        Ok(offsets) => Json(json!({
+            "documents": index.num_docs(),
+            "offsets": offsets,
+        }))
+        .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+    }
+}
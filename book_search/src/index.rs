@@ -0,0 +1,488 @@
+use std::{collections::HashMap, fs, path::Path, sync::Mutex};
+
+use common::{events::dto::CreatedBook, isbn};
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    collector::{Count, FacetCollector, FacetCounts, TopDocs},
+    directory::{error::OpenDirectoryError, MmapDirectory},
+    doc,
+    query::{
+        AllQuery, BooleanQuery, FuzzyTermQuery, Occur, Query, QueryParser, QueryParserError,
+        TermQuery,
+    },
+    schema::{
+        Facet, FacetOptions, Field, IndexRecordOption, Schema, INDEXED, STORED, STRING, TEXT,
+    },
+    Index, IndexReader, IndexWriter, ReloadPolicy, TantivyError, Term,
+};
+use thiserror::Error;
+
+const WRITER_MEMORY_BYTES: usize = 50_000_000;
+
+#[derive(Error, Debug)]
+pub enum IndexError {
+    #[error("Tantivy error")]
+    TantivyError(#[from] TantivyError),
+
+    #[error("Directory error")]
+    DirectoryError(#[from] OpenDirectoryError),
+
+    #[error("Invalid query: {0}")]
+    QueryError(#[from] QueryParserError),
+
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Serialization error")]
+    SerializationError(#[from] serde_json::Error),
+}
+
+struct Fields {
+    id: Field,
+    title: Field,
+    isbn: Field,
+    /// The normalized ISBN, matched exactly.
+    isbn_key: Field,
+    group: Field,
+    publisher: Field,
+    version: Field,
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        id: builder.add_i64_field("id", INDEXED | STORED),
+        title: builder.add_text_field("title", TEXT | STORED),
+        isbn: builder.add_text_field("isbn", STORED),
+        isbn_key: builder.add_text_field("isbn_key", STRING),
+        group: builder.add_facet_field("group", FacetOptions::default()),
+        publisher: builder.add_facet_field("publisher", FacetOptions::default()),
+        version: builder.add_i64_field("version", STORED),
+    };
+    (builder.build(), fields)
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct SearchRequest {
+    /// Words of the title, or an ISBN. Everything matches when empty.
+    #[serde(default)]
+    pub q: String,
+    /// Match title words within a few typos of the query's.
+    #[serde(default)]
+    pub fuzzy: bool,
+    /// Only books of this ISBN registration group, such as `978-0`.
+    pub group: Option<String>,
+    /// Only books of this ISBN publisher prefix, such as `978-0-306`.
+    pub publisher: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SearchHit {
+    pub id: i64,
+    pub title: String,
+    pub isbn: String,
+    pub score: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SearchResults {
+    pub total: usize,
+    pub hits: Vec<SearchHit>,
+    /// Matches by registration group, most first.
+    pub groups: Vec<FacetCount>,
+    /// Matches by publisher prefix, most first.
+    pub publishers: Vec<FacetCount>,
+}
+
+/// A Tantivy index of books, one document per book id.
+///
+/// The next offset to read of each partition is stored as the payload of every
+/// commit, so the documents and the offsets they cover are always in step.
+pub struct BookIndex {
+    index: Index,
+    fields: Fields,
+    writer: Mutex<IndexWriter>,
+    reader: IndexReader,
+    /// Versions of the books upserted since the last commit, which the reader
+    /// doesn't see yet.
+    uncommitted_versions: Mutex<HashMap<i32, i32>>,
+}
+
+impl BookIndex {
+    /// Opens the index in `path`, creating it when the directory holds none.
+    pub fn open(path: &Path) -> Result<Self, IndexError> {
+        fs::create_dir_all(path)?;
+        let (schema, fields) = schema();
+        let index = Index::open_or_create(MmapDirectory::open(path)?, schema)?;
+        Self::with_index(index, fields)
+    }
+
+    pub fn in_memory() -> Result<Self, IndexError> {
+        let (schema, fields) = schema();
+        Self::with_index(Index::create_in_ram(schema), fields)
+    }
+
+    fn with_index(index: Index, fields: Fields) -> Result<Self, IndexError> {
+        let writer = index.writer(WRITER_MEMORY_BYTES)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::OnCommit)
+            .try_into()?;
+        Ok(Self {
+            index,
+            fields,
+            writer: Mutex::new(writer),
+            reader,
+            uncommitted_versions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The offsets stored with the last commit, empty for a new or cleared index.
+    pub fn offsets(&self) -> Result<HashMap<i32, i64>, IndexError> {
+        match self.index.load_metas()?.payload {
+            Some(payload) => Ok(serde_json::from_str(&payload)?),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    pub fn num_docs(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
+    /// Adds `book`, replacing the document of a book with the same id unless that one
+    /// has a later version. Whether `book` was added.
+    pub fn upsert(&self, book: &CreatedBook) -> Result<bool, IndexError> {
+        let mut uncommitted_versions = self.uncommitted_versions.lock().unwrap();
+        let indexed_version = match uncommitted_versions.get(&book.id()) {
+            Some(version) => Some(*version),
+            None => self.committed_version(book.id())?,
+        };
+        if matches!(indexed_version, Some(version) if version > book.version()) {
+            return Ok(false);
+        }
+        let id = book.id() as i64;
+        let mut document = doc!(
+            self.fields.id => id,
+            self.fields.title => book.title(),
+            self.fields.isbn => book.isbn(),
+            self.fields.isbn_key => isbn::normalize(book.isbn()),
+            self.fields.version => book.version() as i64,
+        );
+        if let Some(parts) = isbn::parse(book.isbn()) {
+            document.add_facet(
+                self.fields.group,
+                Facet::from(format!("/{}", parts.group_prefix()).as_str()),
+            );
+            if let Some(publisher) = parts.publisher_prefix() {
+                document.add_facet(
+                    self.fields.publisher,
+                    Facet::from(format!("/{}", publisher).as_str()),
+                );
+            }
+        }
+        let writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_i64(self.fields.id, id));
+        writer.add_document(document)?;
+        uncommitted_versions.insert(book.id(), book.version());
+        Ok(true)
+    }
+
+    fn committed_version(&self, id: i32) -> Result<Option<i32>, IndexError> {
+        let searcher = self.reader.searcher();
+        let query = TermQuery::new(
+            Term::from_field_i64(self.fields.id, id as i64),
+            IndexRecordOption::Basic,
+        );
+        let Some((_, address)) = searcher.search(&query, &TopDocs::with_limit(1))?.pop() else {
+            return Ok(None);
+        };
+        Ok(searcher
+            .doc(address)?
+            .get_first(self.fields.version)
+            .and_then(|value| value.as_i64())
+            .map(|version| version as i32))
+    }
+
+    pub fn delete(&self, id: i32) {
+        self.uncommitted_versions.lock().unwrap().remove(&id);
+        self.writer
+            .lock()
+            .unwrap()
+            .delete_term(Term::from_field_i64(self.fields.id, id as i64));
+    }
+
+    /// Makes the changes so far durable and searchable, together with `offsets`.
+    pub fn commit(&self, offsets: &HashMap<i32, i64>) -> Result<(), IndexError> {
+        let mut uncommitted_versions = self.uncommitted_versions.lock().unwrap();
+        let mut writer = self.writer.lock().unwrap();
+        let mut prepared = writer.prepare_commit()?;
+        prepared.set_payload(&serde_json::to_string(offsets)?);
+        prepared.commit()?;
+        self.reader.reload()?;
+        uncommitted_versions.clear();
+        Ok(())
+    }
+
+    /// Removes every document and forgets the offsets, for a rebuild from the
+    /// earliest offset.
+    pub fn clear(&self) -> Result<(), IndexError> {
+        self.writer.lock().unwrap().delete_all_documents()?;
+        self.commit(&HashMap::new())
+    }
+
+    pub fn search(&self, request: &SearchRequest) -> Result<SearchResults, IndexError> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> =
+            vec![(Occur::Must, self.text_query(request)?)];
+        for (field, value) in [
+            (self.fields.group, &request.group),
+            (self.fields.publisher, &request.publisher),
+        ] {
+            if let Some(value) = value {
+                clauses.push((
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_facet(field, &Facet::from(format!("/{}", value).as_str())),
+                        IndexRecordOption::Basic,
+                    )),
+                ));
+            }
+        }
+        let query = BooleanQuery::new(clauses);
+
+        let mut groups = FacetCollector::for_field(self.fields.group);
+        groups.add_facet("/");
+        let mut publishers = FacetCollector::for_field(self.fields.publisher);
+        publishers.add_facet("/");
+        let limit = request.limit.unwrap_or(20).clamp(1, 100);
+
+        let searcher = self.reader.searcher();
+        let (top, total, groups, publishers) = searcher.search(
+            &query,
+            &(TopDocs::with_limit(limit), Count, groups, publishers),
+        )?;
+        let mut hits = Vec::with_capacity(top.len());
+        for (score, address) in top {
+            let document = searcher.doc(address)?;
+            let text = |field| {
+                document
+                    .get_first(field)
+                    .and_then(|value| value.as_text())
+                    .unwrap_or_default()
+                    .to_owned()
+            };
+            hits.push(SearchHit {
+                id: document
+                    .get_first(self.fields.id)
+                    .and_then(|value| value.as_i64())
+                    .unwrap_or_default(),
+                title: text(self.fields.title),
+                isbn: text(self.fields.isbn),
+                score,
+            });
+        }
+        Ok(SearchResults {
+            total,
+            hits,
+            groups: facet_counts(&groups),
+            publishers: facet_counts(&publishers),
+        })
+    }
+
+    /// Title words, in any order, or the exact ISBN.
+    fn text_query(&self, request: &SearchRequest) -> Result<Box<dyn Query>, IndexError> {
+        if request.q.trim().is_empty() {
+            return Ok(Box::new(AllQuery));
+        }
+        let title: Box<dyn Query> = if request.fuzzy {
+            let words: Vec<(Occur, Box<dyn Query>)> = request
+                .q
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(|word| {
+                    let word = word.to_lowercase();
+                    let distance = match word.chars().count() {
+                        0..=2 => 0,
+                        3..=5 => 1,
+                        _ => 2,
+                    };
+                    let query: Box<dyn Query> = Box::new(FuzzyTermQuery::new(
+                        Term::from_field_text(self.fields.title, &word),
+                        distance,
+                        true,
+                    ));
+                    (Occur::Must, query)
+                })
+                .collect();
+            Box::new(BooleanQuery::new(words))
+        } else {
+            let mut parser = QueryParser::for_index(&self.index, vec![self.fields.title]);
+            parser.set_conjunction_by_default();
+            parser.parse_query(&request.q)?
+        };
+        let isbn = Box::new(TermQuery::new(
+            Term::from_field_text(self.fields.isbn_key, &isbn::normalize(&request.q)),
+            IndexRecordOption::Basic,
+        ));
+        Ok(Box::new(BooleanQuery::new(vec![
+            (Occur::Should, title),
+            (Occur::Should, isbn),
+        ])))
+    }
+}
+
+fn facet_counts(counts: &FacetCounts) -> Vec<FacetCount> {
+    let mut counts: Vec<FacetCount> = counts
+        .get("/")
+        .map(|(facet, count)| FacetCount {
+            value: facet.to_path_string().trim_start_matches('/').to_owned(),
+            count,
+        })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use common::events::dto::CreatedBookBuilder;
+
+    use super::*;
+
+    fn book(id: i32, title: &str, isbn: &str) -> CreatedBook {
+        versioned_book(id, title, isbn, 1)
+    }
+
+    fn versioned_book(id: i32, title: &str, isbn: &str, version: i32) -> CreatedBook {
+        CreatedBookBuilder::default()
+            .id(id)
+            .title(title.to_owned())
+            .isbn(isbn.to_owned())
+            .version(version)
+            .build()
+            .unwrap()
+    }
+
+    fn index() -> BookIndex {
+        let index = BookIndex::in_memory().unwrap();
+        index
+            .upsert(&book(1, "Programming Rust", "978-0-306-40615-7"))
+            .unwrap();
+        index
+            .upsert(&book(2, "Rust in Action", "978-1-4028-9462-6"))
+            .unwrap();
+        index
+            .upsert(&book(3, "The Go Programming Language", "978-0-306-40615-7"))
+            .unwrap();
+        index.commit(&HashMap::from([(0, 3)])).unwrap();
+        index
+    }
+
+    #[test]
+    fn upserts_by_id_and_stores_offsets_with_commits() {
+        let index = index();
+        index
+            .upsert(&book(2, "Rust in Action, 2nd", "978-1-4028-9462-6"))
+            .unwrap();
+        index.commit(&HashMap::from([(0, 4)])).unwrap();
+        assert_eq!(3, index.num_docs());
+        assert_eq!(HashMap::from([(0, 4)]), index.offsets().unwrap());
+
+        index.clear().unwrap();
+        assert_eq!(0, index.num_docs());
+        assert!(index.offsets().unwrap().is_empty());
+    }
+
+    #[test]
+    fn discards_stale_versions() {
+        let index = index();
+        let titles = |index: &BookIndex| {
+            index
+                .search(&SearchRequest {
+                    q: "action".to_owned(),
+                    ..SearchRequest::default()
+                })
+                .unwrap()
+                .hits
+                .into_iter()
+                .map(|hit| hit.title)
+                .collect::<Vec<_>>()
+        };
+        assert!(index
+            .upsert(&versioned_book(
+                2,
+                "Rust in Action, 3rd",
+                "978-1-4028-9462-6",
+                3
+            ))
+            .unwrap());
+        assert!(!index
+            .upsert(&versioned_book(
+                2,
+                "Rust in Action, 2nd",
+                "978-1-4028-9462-6",
+                2
+            ))
+            .unwrap());
+        index.commit(&HashMap::from([(0, 5)])).unwrap();
+        assert!(!index
+            .upsert(&book(2, "Rust in Action", "978-1-4028-9462-6"))
+            .unwrap());
+        index.commit(&HashMap::from([(0, 6)])).unwrap();
+        assert_eq!(vec!["Rust in Action, 3rd".to_owned()], titles(&index));
+    }
+
+    #[test]
+    fn searches_with_typos_and_facets() {
+        let index = index();
+        let exact = index
+            .search(&SearchRequest {
+                q: "rust".to_owned(),
+                ..SearchRequest::default()
+            })
+            .unwrap();
+        assert_eq!(2, exact.total);
+
+        let fuzzy = index
+            .search(&SearchRequest {
+                q: "programing".to_owned(),
+                fuzzy: true,
+                ..SearchRequest::default()
+            })
+            .unwrap();
+        assert_eq!(2, fuzzy.total);
+        assert_eq!(
+            vec![FacetCount {
+                value: "978-0".to_owned(),
+                count: 2
+            }],
+            fuzzy.groups
+        );
+
+        let by_group = index
+            .search(&SearchRequest {
+                q: "rust".to_owned(),
+                group: Some("978-1".to_owned()),
+                ..SearchRequest::default()
+            })
+            .unwrap();
+        assert_eq!(
+            vec![2],
+            by_group.hits.iter().map(|hit| hit.id).collect::<Vec<_>>()
+        );
+
+        let by_isbn = index
+            .search(&SearchRequest {
+                q: "9781402894626".to_owned(),
+                ..SearchRequest::default()
+            })
+            .unwrap();
+        assert_eq!(1, by_isbn.total);
+    }
+}
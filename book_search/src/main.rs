@@ -0,0 +1,133 @@
+pub mod http_servers;
+
+use std::{collections::HashMap, fs, path::Path, sync::Arc, time::Duration};
+
+use book_search::index::BookIndex;
+use clap::Parser;
+use common::events::{constants::Topics, dto::CreatedBook, topics::BookCreated};
+use http_servers::start_http_server;
+use kafka::consumer::{Change, KafkaConsumer, RecordMetadata, StartPosition};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+const INDEX_PATH: &str = "book_search_index";
+const COMMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps a Tantivy index of books up to date from book events and serves searches.
+#[derive(Parser)]
+struct Cli {
+    /// Delete the index and rebuild it by replaying the topic from the earliest offset,
+    /// which is also how an index is migrated to a new schema.
+    #[arg(long)]
+    rebuild: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    opentelemetry::global::set_text_map_propagator(opentelemetry_zipkin::Propagator::new());
+    let tracer = opentelemetry_zipkin::new_pipeline()
+        .with_service_name("books_search".to_owned())
+        .with_service_address("127.0.0.1:8092".parse().unwrap())
+        .with_collector_endpoint("http://localhost:9411/api/v2/spans")
+        .install_batch(opentelemetry::runtime::Tokio)
+        .expect("unable to install zipkin tracer");
+    let tracer = tracing_opentelemetry::layer().with_tracer(tracer.clone());
+
+    let subscriber = tracing_subscriber::fmt::layer().json();
+
+    let level = EnvFilter::new("debug");
+
+    tracing_subscriber::registry()
+        .with(subscriber)
+        .with(level)
+        .with(tracer)
+        .init();
+
+    if cli.rebuild && Path::new(INDEX_PATH).exists() {
+        info!("Deleting the index for a rebuild");
+        fs::remove_dir_all(INDEX_PATH)?;
+    }
+    let index = Arc::new(BookIndex::open(Path::new(INDEX_PATH)).expect("Error opening index"));
+
+    // Every instance indexes the whole topic. Documents are upserted by id, so
+    // records redelivered after a crash between an index commit and the Kafka
+    // commit are harmless; the index's own offsets still decide where reading
+    // resumes.
+    let mut offsets = index.offsets().expect("Error reading index offsets");
+    let start_position = if offsets.is_empty() {
+        StartPosition::Earliest
+    } else {
+        StartPosition::Offsets(offsets.clone())
+    };
+    info!("Starting from {:?}", start_position);
+    let kafka_consumer = KafkaConsumer::new(
+        "localhost:9092".to_string(),
+        "book-search-indexer".to_string(),
+        Topics::BookCreated.to_string(),
+        "http://localhost:8081".to_string(),
+    )
+    .with_start_position(start_position)
+    .with_all_partitions()
+    .with_manual_commit();
+    let committer = kafka_consumer.committer();
+
+    tokio::spawn(start_http_server(index.clone()));
+
+    let (sender, mut receiver) = mpsc::unbounded_channel::<(RecordMetadata, Change<CreatedBook>)>();
+    tokio::spawn(async move {
+        info!("Starting book search indexer");
+        kafka_consumer
+            .subscribe_changes::<BookCreated>(sender)
+            .await;
+    });
+
+    let topic = Topics::BookCreated.to_string();
+    let mut commit_interval = tokio::time::interval(COMMIT_INTERVAL);
+    let mut pending: HashMap<i32, i64> = HashMap::new();
+    loop {
+        tokio::select! {
+            received = receiver.recv() => {
+                let Some((metadata, change)) = received else {
+                    break;
+                };
+                match change {
+                    Change::Upsert(book) if book.deleted() => index.delete(book.id()),
+                    Change::Upsert(book) => match index.upsert(&book) {
+                        Ok(true) => {}
+                        Ok(false) => info!(
+                            "Skipping version {} of book {}, a later one is indexed",
+                            book.version(),
+                            book.id()
+                        ),
+                        Err(e) => {
+                            error!("Error indexing book {}: {}", book.id(), e);
+                            break;
+                        }
+                    },
+                    Change::Tombstone(key) => match key.parse() {
+                        Ok(id) => index.delete(id),
+                        Err(_) => error!("Skipping tombstone with key '{}'", key),
+                    },
+                }
+                pending.insert(metadata.partition, metadata.offset + 1);
+            }
+            _ = commit_interval.tick() => {
+                if pending.is_empty() {
+                    continue;
+                }
+                offsets.extend(pending.drain());
+                if let Err(e) = index.commit(&offsets) {
+                    error!("Error committing index: {}", e);
+                    break;
+                }
+                if let Err(e) = committer.commit(&topic, &offsets) {
+                    error!("Error committing offsets: {}", e);
+                }
+            }
+        }
+    }
+    opentelemetry::global::shutdown_tracer_provider();
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use apache_avro::{schema::Name, schema_compatibility::SchemaCompatibility, Schema};
+use strum::{Display, EnumString};
+
+/// Compatibility levels understood by the Schema Registry.
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum CompatibilityLevel {
+    None,
+    Backward,
+    BackwardTransitive,
+    Forward,
+    ForwardTransitive,
+    Full,
+    FullTransitive,
+}
+
+impl CompatibilityLevel {
+    fn checks_backward(&self) -> bool {
+        matches!(
+            self,
+            Self::Backward | Self::BackwardTransitive | Self::Full | Self::FullTransitive
+        )
+    }
+
+    fn checks_forward(&self) -> bool {
+        matches!(
+            self,
+            Self::Forward | Self::ForwardTransitive | Self::Full | Self::FullTransitive
+        )
+    }
+
+    pub fn is_transitive(&self) -> bool {
+        matches!(
+            self,
+            Self::BackwardTransitive | Self::ForwardTransitive | Self::FullTransitive
+        )
+    }
+
+    /// Whether enforcing this level also enforces `required`.
+    pub fn satisfies(&self, required: CompatibilityLevel) -> bool {
+        (!required.checks_backward() || self.checks_backward())
+            && (!required.checks_forward() || self.checks_forward())
+            && (!required.is_transitive() || self.is_transitive())
+    }
+}
+
+/// Checks `new` against `old` at the given level and returns every incompatibility found.
+pub fn check_compatibility(
+    new: &Schema,
+    old: &Schema,
+    level: CompatibilityLevel,
+) -> Result<(), Vec<String>> {
+    let (new, old) = (&inline_references(new), &inline_references(old));
+    let mut errors = Vec::new();
+    if level.checks_backward() && !SchemaCompatibility::can_read(old, new) {
+        errors.push(
+            "backward: data written with the old schema can't be read with the new one".to_owned(),
+        );
+    }
+    if level.checks_forward() && !SchemaCompatibility::can_read(new, old) {
+        errors.push(
+            "forward: data written with the new schema can't be read with the old one".to_owned(),
+        );
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// `schema` with references to named types replaced by their definitions, which
+/// `SchemaCompatibility` otherwise compares by name only. Recursive references stay.
+/// Logical types become the types they annotate, which readers fall back to and which,
+/// unlike logical types, `SchemaCompatibility` can compare.
+fn inline_references(schema: &Schema) -> Schema {
+    let mut definitions = HashMap::new();
+    collect_definitions(schema, &mut definitions);
+    inline(schema, &definitions, &mut Vec::new())
+}
+
+fn collect_definitions(schema: &Schema, definitions: &mut HashMap<Name, Schema>) {
+    match schema {
+        Schema::Record { name, fields, .. } => {
+            definitions.insert(name.clone(), schema.clone());
+            for field in fields {
+                collect_definitions(&field.schema, definitions);
+            }
+        }
+        Schema::Enum { name, .. } | Schema::Fixed { name, .. } => {
+            definitions.insert(name.clone(), schema.clone());
+        }
+        Schema::Array(items) | Schema::Map(items) => collect_definitions(items, definitions),
+        Schema::Union(union) => union
+            .variants()
+            .iter()
+            .for_each(|variant| collect_definitions(variant, definitions)),
+        _ => {}
+    }
+}
+
+fn inline(
+    schema: &Schema,
+    definitions: &HashMap<Name, Schema>,
+    expanding: &mut Vec<Name>,
+) -> Schema {
+    match schema {
+        Schema::Ref { name } if !expanding.contains(name) => match definitions.get(name) {
+            Some(definition) => inline(definition, definitions, expanding),
+            None => schema.clone(),
+        },
+        Schema::Record { name, .. } => {
+            let mut record = schema.clone();
+            expanding.push(name.clone());
+            if let Schema::Record { fields, .. } = &mut record {
+                for field in fields {
+                    field.schema = inline(&field.schema, definitions, expanding);
+                }
+            }
+            expanding.pop();
+            record
+        }
+        Schema::Array(items) => Schema::Array(Box::new(inline(items, definitions, expanding))),
+        Schema::Map(values) => Schema::Map(Box::new(inline(values, definitions, expanding))),
+        Schema::Union(union) => {
+            let variants: Vec<Schema> = union
+                .variants()
+                .iter()
+                .map(|variant| inline(variant, definitions, expanding))
+                .collect();
+            // Unions can only be built by parsing, which fails on recursive references.
+            serde_json::to_value(variants)
+                .ok()
+                .and_then(|variants| Schema::parse(&variants).ok())
+                .unwrap_or_else(|| schema.clone())
+        }
+        Schema::Date | Schema::TimeMillis => Schema::Int,
+        Schema::TimeMicros | Schema::TimestampMillis | Schema::TimestampMicros => Schema::Long,
+        Schema::Uuid => Schema::String,
+        _ => schema.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(raw: &str) -> Schema {
+        Schema::parse_str(raw).unwrap()
+    }
+
+    #[test]
+    fn adding_a_field_with_default_is_backward_compatible() {
+        let old =
+            schema(r#"{"type":"record","name":"Book","fields":[{"name":"id","type":"int"}]}"#);
+        let new = schema(
+            r#"{"type":"record","name":"Book","fields":[{"name":"id","type":"int"},{"name":"title","type":["null","string"],"default":null}]}"#,
+        );
+        assert!(check_compatibility(&new, &old, CompatibilityLevel::Full).is_ok());
+    }
+
+    #[test]
+    fn adding_a_required_field_is_not_backward_compatible() {
+        let old =
+            schema(r#"{"type":"record","name":"Book","fields":[{"name":"id","type":"int"}]}"#);
+        let new = schema(
+            r#"{"type":"record","name":"Book","fields":[{"name":"id","type":"int"},{"name":"title","type":"string"}]}"#,
+        );
+        assert!(check_compatibility(&new, &old, CompatibilityLevel::Backward).is_err());
+        assert!(check_compatibility(&new, &old, CompatibilityLevel::Forward).is_ok());
+    }
+
+    #[test]
+    fn type_promotion_is_one_way() {
+        let old =
+            schema(r#"{"type":"record","name":"Book","fields":[{"name":"id","type":"int"}]}"#);
+        let new =
+            schema(r#"{"type":"record","name":"Book","fields":[{"name":"id","type":"long"}]}"#);
+        assert!(check_compatibility(&new, &old, CompatibilityLevel::Backward).is_ok());
+        assert!(check_compatibility(&new, &old, CompatibilityLevel::Full).is_err());
+    }
+
+    #[test]
+    fn renamed_records_resolve_through_named_references() {
+        let old = schema(
+            r#"{"type":"record","name":"Book","namespace":"a","fields":[
+                {"name":"author","type":{"type":"record","name":"Author","fields":[{"name":"id","type":"int"}]}},
+                {"name":"editor","type":"Author"}]}"#,
+        );
+        let new = schema(
+            r#"{"type":"record","name":"Book","namespace":"a","fields":[
+                {"name":"author","type":{"type":"record","name":"Author","fields":[{"name":"id","type":"long"}]}},
+                {"name":"editor","type":"Author"}]}"#,
+        );
+        assert!(check_compatibility(&new, &old, CompatibilityLevel::Backward).is_ok());
+        assert!(check_compatibility(&new, &old, CompatibilityLevel::Forward).is_err());
+    }
+
+    #[test]
+    fn references_in_unions_are_resolved() {
+        let old = schema(
+            r#"{"type":"record","name":"Book","fields":[
+                {"name":"author","type":{"type":"record","name":"Author","fields":[{"name":"id","type":"int"}]}},
+                {"name":"editor","type":["null","Author"],"default":null}]}"#,
+        );
+        let new = schema(
+            r#"{"type":"record","name":"Book","fields":[
+                {"name":"author","type":{"type":"record","name":"Author","fields":[{"name":"id","type":"int"}]}},
+                {"name":"editor","type":["null","Author"],"default":null}]}"#,
+        );
+        assert!(check_compatibility(&new, &old, CompatibilityLevel::FullTransitive).is_ok());
+    }
+
+    #[test]
+    fn logical_types_compare_as_their_underlying_types() {
+        let date = schema(
+            r#"{"type":"record","name":"Book","fields":[{"name":"published_on","type":["null",{"type":"int","logicalType":"date"}],"default":null}]}"#,
+        );
+        let int = schema(
+            r#"{"type":"record","name":"Book","fields":[{"name":"published_on","type":["null","int"],"default":null}]}"#,
+        );
+        let string = schema(
+            r#"{"type":"record","name":"Book","fields":[{"name":"published_on","type":["null","string"],"default":null}]}"#,
+        );
+        assert!(check_compatibility(&date, &date, CompatibilityLevel::Full).is_ok());
+        assert!(check_compatibility(&date, &int, CompatibilityLevel::Full).is_ok());
+        assert!(check_compatibility(&date, &string, CompatibilityLevel::Backward).is_err());
+    }
+
+    #[test]
+    fn stronger_levels_satisfy_weaker_ones() {
+        use CompatibilityLevel::*;
+        assert!(FullTransitive.satisfies(Backward));
+        assert!(Full.satisfies(Forward));
+        assert!(Backward.satisfies(None));
+        assert!(!Backward.satisfies(BackwardTransitive));
+        assert!(!Forward.satisfies(Full));
+        assert!(!None.satisfies(Backward));
+    }
+}
@@ -1,6 +1,7 @@
-use strum::Display;
+use strum::{Display, EnumIter, EnumString};
 
-#[derive(Display)]
+#[derive(Display, EnumIter, EnumString, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Topics {
     BookCreated,
+    AnalyticsAlert,
 }
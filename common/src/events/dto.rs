@@ -1,10 +1,218 @@
-use apache_avro::AvroSchema;
+use std::collections::HashMap;
+
+use apache_avro::schema::{derive::AvroSchemaComponent, Name, Namespace};
+use apache_avro::{AvroSchema, Schema};
 use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
 
+use super::compatibility::CompatibilityLevel;
+
 #[derive(Serialize, Deserialize, Builder, Clone, Debug, AvroSchema)]
 pub struct CreatedBook {
     id: i32,
     title: String,
     isbn: String,
+    #[serde(default)]
+    #[avro(default = "null")]
+    #[builder(default)]
+    publisher_id: Option<i32>,
+    #[serde(default)]
+    #[avro(default = "[]")]
+    #[builder(default)]
+    author_ids: Vec<i32>,
+    #[serde(default)]
+    #[avro(default = "[]")]
+    #[builder(default)]
+    genre_ids: Vec<i32>,
+    #[serde(default)]
+    #[avro(default = "null")]
+    #[builder(default)]
+    published_on: Option<Date>,
+    /// BCP 47 language tag.
+    #[serde(default)]
+    #[avro(default = "null")]
+    #[builder(default)]
+    language: Option<String>,
+    #[serde(default)]
+    #[avro(default = "null")]
+    #[builder(default)]
+    page_count: Option<i32>,
+    #[serde(default)]
+    #[avro(default = "null")]
+    #[builder(default)]
+    edition: Option<String>,
+    #[serde(default)]
+    #[avro(default = "null")]
+    #[builder(default)]
+    description: Option<String>,
+    #[serde(default)]
+    #[avro(default = "null")]
+    #[builder(default)]
+    cover_url: Option<String>,
+    /// The book's version, so that consumers can discard events older than what
+    /// they have seen.
+    #[serde(default = "first_version")]
+    #[avro(default = "1")]
+    #[builder(default = "1")]
+    version: i32,
+    /// Soft-deleted, so restorable until purged. Purging publishes a tombstone.
+    #[serde(default)]
+    #[avro(default = "false")]
+    #[builder(default)]
+    deleted: bool,
+}
+
+/// A calendar date as the days since 1970-01-01, written with the Avro `date`
+/// logical type.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Date(i32);
+
+impl Date {
+    pub fn from_days_since_epoch(days: i32) -> Self {
+        Self(days)
+    }
+
+    pub fn days_since_epoch(self) -> i32 {
+        self.0
+    }
+}
+
+impl AvroSchemaComponent for Date {
+    fn get_schema_in_ctxt(_: &mut HashMap<Name, Schema>, _: &Namespace) -> Schema {
+        Schema::Date
+    }
+}
+
+fn first_version() -> i32 {
+    1
+}
+
+impl CreatedBook {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn isbn(&self) -> &str {
+        &self.isbn
+    }
+
+    pub fn publisher_id(&self) -> Option<i32> {
+        self.publisher_id
+    }
+
+    pub fn author_ids(&self) -> &[i32] {
+        &self.author_ids
+    }
+
+    pub fn genre_ids(&self) -> &[i32] {
+        &self.genre_ids
+    }
+
+    pub fn published_on(&self) -> Option<Date> {
+        self.published_on
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    pub fn page_count(&self) -> Option<i32> {
+        self.page_count
+    }
+
+    pub fn edition(&self) -> Option<&str> {
+        self.edition.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn cover_url(&self) -> Option<&str> {
+        self.cover_url.as_deref()
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    pub fn deleted(&self) -> bool {
+        self.deleted
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, AvroSchema)]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+/// A rule of the analytics service starting or ceasing to fire, over the windows
+/// ending at `window_end`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, AvroSchema)]
+pub struct AnalyticsAlert {
+    pub rule: String,
+    pub status: AlertStatus,
+    pub severity: String,
+    pub message: String,
+    /// The count, or z-score, the rule evaluated.
+    pub value: f64,
+    /// The bound `value` was compared with.
+    pub threshold: f64,
+    pub window_start: i64,
+    pub window_end: i64,
+    /// Epoch milliseconds.
+    pub raised_at: i64,
+}
+
+/// Every event type with the compatibility level its snapshot in `schemas/` is held to.
+pub fn event_schemas() -> Vec<(&'static str, Schema, CompatibilityLevel)> {
+    vec![
+        (
+            "CreatedBook",
+            CreatedBook::get_schema(),
+            CompatibilityLevel::Backward,
+        ),
+        (
+            "AnalyticsAlert",
+            AnalyticsAlert::get_schema(),
+            CompatibilityLevel::Backward,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use apache_avro::{from_avro_datum, from_value, to_avro_datum, to_value, types::Value};
+
+    use super::*;
+
+    #[test]
+    fn test_published_on_is_an_avro_date() {
+        let schema = CreatedBook::get_schema();
+        let book = CreatedBookBuilder::default()
+            .id(1)
+            .title("Title".to_owned())
+            .isbn("isbn".to_owned())
+            .published_on(Some(Date::from_days_since_epoch(19_875)))
+            .build()
+            .unwrap();
+        let datum = to_avro_datum(&schema, to_value(&book).unwrap()).unwrap();
+        let value = from_avro_datum(&schema, &mut datum.as_slice(), None).unwrap();
+        let Value::Record(fields) = &value else {
+            panic!("expected a record");
+        };
+        assert!(fields.iter().any(|(name, value)| name == "published_on"
+            && *value == Value::Union(1, Box::new(Value::Date(19_875)))));
+        let decoded: CreatedBook = from_value(&value).unwrap();
+        assert_eq!(
+            Some(Date::from_days_since_epoch(19_875)),
+            decoded.published_on()
+        );
+    }
 }
@@ -1,2 +1,4 @@
+pub mod compatibility;
 pub mod constants;
 pub mod dto;
+pub mod topics;
@@ -0,0 +1,173 @@
+use std::fmt::Debug;
+
+use apache_avro::{AvroSchema, Schema};
+use serde::{Deserialize, Serialize};
+use strum::{Display, IntoEnumIterator};
+
+use super::{
+    constants::Topics,
+    dto::{AnalyticsAlert as AnalyticsAlertPayload, CreatedBook},
+};
+
+#[derive(Display, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CleanupPolicy {
+    #[strum(serialize = "delete")]
+    Delete,
+    #[strum(serialize = "compact")]
+    Compact,
+    #[strum(serialize = "compact,delete")]
+    CompactDelete,
+}
+
+/// How the key of a topic is put on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// The key's `ToString` output, as raw UTF-8.
+    Plain,
+    /// Avro, registered under the key subject of the topic's strategy.
+    Avro,
+}
+
+/// How the Schema Registry subject of a key or a value is derived.
+///
+/// The same strategy is used both when registering a schema and when
+/// encoding a record, so the two always agree on the subject.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SubjectStrategy {
+    /// `<topic>-key` or `<topic>-value`.
+    #[default]
+    Topic,
+    /// The fully qualified name of the Avro record.
+    Record,
+    /// `<topic>-<fully qualified record name>`.
+    TopicRecord,
+}
+
+/// Strategies used for the key and the value of a record.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubjectStrategies {
+    pub key: SubjectStrategy,
+    pub value: SubjectStrategy,
+}
+
+impl SubjectStrategies {
+    /// `<topic>-key` and `<topic>-value`, the default of every topic.
+    pub const TOPIC: SubjectStrategies = SubjectStrategies {
+        key: SubjectStrategy::Topic,
+        value: SubjectStrategy::Topic,
+    };
+
+    pub fn new(key: SubjectStrategy, value: SubjectStrategy) -> Self {
+        Self { key, value }
+    }
+}
+
+/// Broker-side configuration of a topic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopicDefinition {
+    pub topic: Topics,
+    pub partitions: i32,
+    pub replication_factor: i32,
+    /// `None` keeps records forever.
+    pub retention_ms: Option<i64>,
+    pub cleanup_policy: CleanupPolicy,
+}
+
+impl TopicDefinition {
+    pub fn name(&self) -> String {
+        self.topic.to_string()
+    }
+
+    /// Topic-level configs as they are set on the broker.
+    pub fn configs(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("retention.ms", self.retention_ms.unwrap_or(-1).to_string()),
+            ("cleanup.policy", self.cleanup_policy.to_string()),
+        ]
+    }
+}
+
+/// A topic bound to the types of its keys and payloads.
+pub trait Topic {
+    type Key: Serialize + for<'a> Deserialize<'a> + AvroSchema + ToString + Send + Sync;
+    type Payload: Serialize + for<'a> Deserialize<'a> + AvroSchema + Clone + Debug + Send + Sync;
+
+    const KEY_FORMAT: KeyFormat;
+
+    /// How the subjects of the key and payload schemas are named, when publishing,
+    /// registering and checking them alike.
+    const SUBJECT_STRATEGIES: SubjectStrategies = SubjectStrategies::TOPIC;
+
+    fn definition() -> TopicDefinition;
+
+    fn name() -> String {
+        Self::definition().name()
+    }
+}
+
+pub struct BookCreated;
+
+impl Topic for BookCreated {
+    type Key = i32;
+    type Payload = CreatedBook;
+
+    const KEY_FORMAT: KeyFormat = KeyFormat::Plain;
+
+    fn definition() -> TopicDefinition {
+        Topics::BookCreated.definition()
+    }
+}
+
+pub struct AnalyticsAlert;
+
+impl Topic for AnalyticsAlert {
+    /// The rule name, so the alerts of a rule stay in order.
+    type Key = String;
+    type Payload = AnalyticsAlertPayload;
+
+    const KEY_FORMAT: KeyFormat = KeyFormat::Plain;
+
+    fn definition() -> TopicDefinition {
+        Topics::AnalyticsAlert.definition()
+    }
+}
+
+impl Topics {
+    pub fn payload_schema(&self) -> Schema {
+        match self {
+            Topics::BookCreated => <BookCreated as Topic>::Payload::get_schema(),
+            Topics::AnalyticsAlert => <AnalyticsAlert as Topic>::Payload::get_schema(),
+        }
+    }
+
+    pub fn subject_strategies(&self) -> SubjectStrategies {
+        match self {
+            Topics::BookCreated => BookCreated::SUBJECT_STRATEGIES,
+            Topics::AnalyticsAlert => AnalyticsAlert::SUBJECT_STRATEGIES,
+        }
+    }
+
+    pub fn definition(&self) -> TopicDefinition {
+        match self {
+            Topics::BookCreated => TopicDefinition {
+                topic: *self,
+                partitions: 3,
+                replication_factor: 1,
+                retention_ms: None,
+                cleanup_policy: CleanupPolicy::Compact,
+            },
+            Topics::AnalyticsAlert => TopicDefinition {
+                topic: *self,
+                partitions: 1,
+                replication_factor: 1,
+                retention_ms: Some(30 * 86_400_000),
+                cleanup_policy: CleanupPolicy::Delete,
+            },
+        }
+    }
+}
+
+/// Definitions of every topic the services produce to or consume from.
+pub fn definitions() -> Vec<TopicDefinition> {
+    Topics::iter().map(|topic| topic.definition()).collect()
+}
@@ -0,0 +1,206 @@
+/// Ranges over the seven digits following a prefix, with how many of them the
+/// next element takes. A length of 0 marks a range that is not assigned.
+type Ranges = &'static [(u32, u32, usize)];
+
+const GROUPS_978: Ranges = &[
+    (0, 5_999_999, 1),
+    (6_000_000, 6_499_999, 3),
+    (6_500_000, 6_599_999, 2),
+    (6_600_000, 6_999_999, 0),
+    (7_000_000, 7_999_999, 1),
+    (8_000_000, 9_499_999, 2),
+    (9_500_000, 9_899_999, 3),
+    (9_900_000, 9_989_999, 4),
+    (9_990_000, 9_999_999, 5),
+];
+
+const GROUPS_979: Ranges = &[
+    (0, 999_999, 0),
+    (1_000_000, 1_299_999, 2),
+    (1_300_000, 7_999_999, 0),
+    (8_000_000, 8_999_999, 1),
+    (9_000_000, 9_999_999, 0),
+];
+
+const REGISTRANTS_978_0: Ranges = &[
+    (0, 1_999_999, 2),
+    (2_000_000, 6_999_999, 3),
+    (7_000_000, 8_499_999, 4),
+    (8_500_000, 8_999_999, 5),
+    (9_000_000, 9_499_999, 6),
+    (9_500_000, 9_999_999, 7),
+];
+
+const REGISTRANTS_978_1: Ranges = &[
+    (0, 999_999, 2),
+    (1_000_000, 3_999_999, 3),
+    (4_000_000, 5_499_999, 4),
+    (5_500_000, 8_697_999, 5),
+    (8_698_000, 9_989_999, 6),
+    (9_990_000, 9_999_999, 7),
+];
+
+/// Registrant ranges are only known for the English-language groups; books from
+/// other groups are still attributed to their group.
+fn registrant_ranges(prefix: &str, group: &str) -> Option<Ranges> {
+    match (prefix, group) {
+        ("978", "0") => Some(REGISTRANTS_978_0),
+        ("978", "1") => Some(REGISTRANTS_978_1),
+        _ => None,
+    }
+}
+
+/// An ISBN split into the elements analytics break counts down by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IsbnParts {
+    pub prefix: String,
+    pub group: String,
+    pub registrant: Option<String>,
+}
+
+impl IsbnParts {
+    /// The registration group, such as `978-0`.
+    pub fn group_prefix(&self) -> String {
+        format!("{}-{}", self.prefix, self.group)
+    }
+
+    /// The publisher prefix, such as `978-0-306`, when the group's ranges are known.
+    pub fn publisher_prefix(&self) -> Option<String> {
+        self.registrant
+            .as_ref()
+            .map(|registrant| format!("{}-{}-{}", self.prefix, self.group, registrant))
+    }
+}
+
+/// Strips hyphens and spaces and upper-cases a trailing `x`, without validating.
+pub fn normalize(isbn: &str) -> String {
+    isbn.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Whether `isbn` is a well-formed ISBN-10 or ISBN-13 with a correct check digit.
+pub fn is_valid(isbn: &str) -> bool {
+    let isbn = normalize(isbn);
+    let digits: Vec<u32> = isbn.chars().filter_map(|c| c.to_digit(10)).collect();
+    match isbn.len() {
+        10 if digits.len() == 10 || (digits.len() == 9 && isbn.ends_with('X')) => {
+            let check = digits.get(9).copied().unwrap_or(10);
+            let sum: u32 = digits[..9]
+                .iter()
+                .enumerate()
+                .map(|(i, d)| (10 - i as u32) * d)
+                .sum();
+            (sum + check).is_multiple_of(11)
+        }
+        13 if digits.len() == 13 => {
+            let sum: u32 = digits
+                .iter()
+                .enumerate()
+                .map(|(i, d)| if i % 2 == 0 { *d } else { 3 * d })
+                .sum();
+            sum.is_multiple_of(10)
+        }
+        _ => false,
+    }
+}
+
+/// The ISBN-13 form of a valid ISBN-10 or ISBN-13, without hyphens.
+pub fn to_isbn13(isbn: &str) -> Option<String> {
+    if !is_valid(isbn) {
+        return None;
+    }
+    let isbn = normalize(isbn);
+    if isbn.len() == 13 {
+        return Some(isbn);
+    }
+    let body = format!("978{}", &isbn[..9]);
+    let sum: u32 = body
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d } else { 3 * d })
+        .sum();
+    Some(format!("{}{}", body, (10 - sum % 10) % 10))
+}
+
+/// The form books are unique by: the ISBN-13 form of a valid ISBN, so that an ISBN-10
+/// matches its ISBN-13, and the normalized form of anything else.
+pub fn canonical(isbn: &str) -> String {
+    to_isbn13(isbn).unwrap_or_else(|| normalize(isbn))
+}
+
+fn element(digits: &str, ranges: Ranges) -> Option<&str> {
+    let mut padded = digits.chars().take(7).collect::<String>();
+    while padded.len() < 7 {
+        padded.push('0');
+    }
+    let value: u32 = padded.parse().ok()?;
+    let (_, _, len) = ranges
+        .iter()
+        .find(|(low, high, _)| (*low..=*high).contains(&value))?;
+    (*len > 0 && *len <= digits.len()).then(|| &digits[..*len])
+}
+
+/// Splits a valid ISBN into prefix, registration group and, where known, registrant.
+pub fn parse(isbn: &str) -> Option<IsbnParts> {
+    let isbn = to_isbn13(isbn)?;
+    let (prefix, rest) = isbn.split_at(3);
+    let rest = &rest[..rest.len() - 1];
+    let groups = match prefix {
+        "978" => GROUPS_978,
+        "979" => GROUPS_979,
+        _ => return None,
+    };
+    let group = element(rest, groups)?;
+    let registrant = registrant_ranges(prefix, group)
+        .and_then(|ranges| element(&rest[group.len()..], ranges))
+        .map(str::to_owned);
+    Some(IsbnParts {
+        prefix: prefix.to_owned(),
+        group: group.to_owned(),
+        registrant,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_check_digits() {
+        assert!(is_valid("978-0-306-40615-7"));
+        assert!(is_valid("0-306-40615-2"));
+        assert!(is_valid("0-8044-2957-X"));
+        assert!(!is_valid("978-0-306-40615-8"));
+        assert!(!is_valid("not an isbn"));
+    }
+
+    #[test]
+    fn converts_isbn10_to_isbn13() {
+        assert_eq!(
+            Some("9780306406157".to_owned()),
+            to_isbn13("0-306-40615-2")
+        );
+    }
+
+    #[test]
+    fn canonical_forms_match_across_isbn10_and_isbn13() {
+        assert_eq!(canonical("0-306-40615-2"), canonical("978 0 306 40615 7"));
+        assert_eq!("9780306406157", canonical("0-306-40615-2"));
+        assert_eq!("ISDB", canonical("is-db"));
+        assert_eq!("ISDB", canonical("IS\u{a0}DB"));
+    }
+
+    #[test]
+    fn splits_group_and_publisher() {
+        let parts = parse("978-0-306-40615-7").unwrap();
+        assert_eq!("978-0", parts.group_prefix());
+        assert_eq!(Some("978-0-306".to_owned()), parts.publisher_prefix());
+
+        let parts = parse("978-3-16-148410-0").unwrap();
+        assert_eq!("978-3", parts.group_prefix());
+        assert_eq!(None, parts.publisher_prefix());
+    }
+}
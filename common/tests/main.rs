@@ -0,0 +1 @@
+pub mod schema_snapshot_test;
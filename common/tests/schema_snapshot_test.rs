@@ -0,0 +1,35 @@
+use std::{env, fs, path::PathBuf};
+
+use apache_avro::Schema;
+use common::events::{compatibility::check_compatibility, dto::event_schemas};
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("schemas")
+        .join(format!("{}.avsc", name))
+}
+
+/// Fails when an event schema changes incompatibly with its committed snapshot.
+/// Run with `UPDATE_SCHEMA_SNAPSHOTS=1` to accept a compatible change.
+#[test]
+fn test_event_schemas_match_snapshots() {
+    let update = env::var("UPDATE_SCHEMA_SNAPSHOTS").is_ok();
+    let mut failures = Vec::new();
+    for (name, schema, level) in event_schemas() {
+        let path = snapshot_path(name);
+        let current = serde_json::to_string_pretty(&schema).unwrap();
+        match fs::read_to_string(&path) {
+            Ok(snapshot) => {
+                let snapshot = Schema::parse_str(&snapshot).unwrap();
+                if let Err(errors) = check_compatibility(&schema, &snapshot, level) {
+                    failures.push(format!("{} ({}): {}", name, level, errors.join(", ")));
+                } else if update {
+                    fs::write(&path, current + "\n").unwrap();
+                }
+            }
+            Err(_) if update => fs::write(&path, current + "\n").unwrap(),
+            Err(_) => failures.push(format!("{}: missing snapshot {}", name, path.display())),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -1,3 +1,5 @@
+pub mod processed_events;
+
 use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr};
 use std::time::Duration;
 use tracing::log;
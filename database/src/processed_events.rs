@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, Statement};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Records `event_id` as processed by `consumer_group`, returning `false` when it
+/// already was. Run it on the transaction holding the handler's side effects.
+///
+/// The `processed_event` table is created by the book_api migrations.
+pub async fn mark_processed<C: ConnectionTrait>(
+    conn: &C,
+    consumer_group: &str,
+    event_id: &str,
+) -> Result<bool, DbErr> {
+    let result = conn
+        .execute(Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            "INSERT INTO processed_event (consumer_group, event_id) VALUES ($1, $2) \
+             ON CONFLICT DO NOTHING",
+            [consumer_group.into(), event_id.into()],
+        ))
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Keeps the undecodable record `event_id` aside for `consumer_group`, with why it
+/// couldn't be decoded and its payload, so consumption can move past it. Returns
+/// `false` when it already was.
+///
+/// The `dead_letter_event` table is created by the book_api migrations.
+pub async fn dead_letter<C: ConnectionTrait>(
+    conn: &C,
+    consumer_group: &str,
+    event_id: &str,
+    error: &str,
+    payload: Option<&[u8]>,
+) -> Result<bool, DbErr> {
+    let result = conn
+        .execute(Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            "INSERT INTO dead_letter_event (consumer_group, event_id, error, payload) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+            [
+                consumer_group.into(),
+                event_id.into(),
+                error.into(),
+                payload.map(<[u8]>::to_vec).into(),
+            ],
+        ))
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Deletes records processed more than `ttl` ago, returning how many were removed.
+pub async fn delete_processed_before<C: ConnectionTrait>(
+    conn: &C,
+    ttl: Duration,
+) -> Result<u64, DbErr> {
+    let result = conn
+        .execute(Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            "DELETE FROM processed_event WHERE processed_at < now() - $1 * interval '1 second'",
+            [(ttl.as_secs() as i64).into()],
+        ))
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Runs `delete_processed_before` every `every`. The TTL must outlast the longest
+/// redelivery window, or a late duplicate is processed again.
+pub fn spawn_processed_event_cleanup(
+    db: DatabaseConnection,
+    ttl: Duration,
+    every: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        loop {
+            interval.tick().await;
+            match delete_processed_before(&db, ttl).await {
+                Ok(deleted) => info!("Deleted {} processed events older than {:?}", deleted, ttl),
+                Err(e) => error!("Error deleting processed events: {}", e),
+            }
+        }
+    })
+}
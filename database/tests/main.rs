@@ -1 +1,2 @@
 pub mod db_test;
+pub mod processed_events_test;
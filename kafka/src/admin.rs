@@ -0,0 +1,444 @@
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
+
+use common::events::topics::TopicDefinition;
+use rdkafka::{
+    admin::{
+        AdminClient, AdminOptions, AlterConfig, NewPartitions, NewTopic, ResourceSpecifier,
+        TopicReplication,
+    },
+    client::DefaultClientContext,
+    error::KafkaError,
+    types::RDKafkaErrorCode,
+    ClientConfig,
+};
+use thiserror::Error;
+use tracing::{info, warn};
+
+#[derive(Error, Debug)]
+pub enum AdminError {
+    #[error("Kafka error")]
+    KafkaError(#[from] KafkaError),
+
+    #[error("Admin operation on {0} failed: {1}")]
+    OperationError(String, RDKafkaErrorCode),
+
+    #[error("Metadata task error")]
+    TaskError(#[from] tokio::task::JoinError),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigChange {
+    pub name: String,
+    pub current: Option<String>,
+    pub desired: String,
+}
+
+/// A change needed to bring a topic in line with its definition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlannedChange {
+    CreateTopic {
+        topic: String,
+        partitions: i32,
+        replication_factor: i32,
+        configs: Vec<(String, String)>,
+    },
+    IncreasePartitions {
+        topic: String,
+        from: i32,
+        to: i32,
+    },
+    UpdateConfigs {
+        topic: String,
+        changes: Vec<ConfigChange>,
+        /// Every managed config of the topic; `AlterConfigs` resets configs it is not given.
+        configs: Vec<(String, String)>,
+    },
+}
+
+impl fmt::Display for PlannedChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlannedChange::CreateTopic {
+                topic,
+                partitions,
+                replication_factor,
+                configs,
+            } => write!(
+                f,
+                "create topic {} with {} partitions, replication factor {}, configs {:?}",
+                topic, partitions, replication_factor, configs
+            ),
+            PlannedChange::IncreasePartitions { topic, from, to } => write!(
+                f,
+                "increase partitions of {} from {} to {}",
+                topic, from, to
+            ),
+            PlannedChange::UpdateConfigs { topic, changes, .. } => {
+                write!(f, "update configs of {}:", topic)?;
+                for change in changes {
+                    write!(
+                        f,
+                        " {}: {} -> {}",
+                        change.name,
+                        change.current.as_deref().unwrap_or("<unset>"),
+                        change.desired
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartitionInfo {
+    pub id: i32,
+    pub leader: i32,
+    pub replicas: Vec<i32>,
+    pub isr: Vec<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopicInfo {
+    pub name: String,
+    pub partitions: Vec<PartitionInfo>,
+}
+
+pub struct KafkaAdmin {
+    admin: Arc<AdminClient<DefaultClientContext>>,
+    timeout: Duration,
+}
+
+impl KafkaAdmin {
+    pub fn new(bootstrap_servers: String) -> Self {
+        let admin: AdminClient<DefaultClientContext> = ClientConfig::new()
+            .set("bootstrap.servers", bootstrap_servers)
+            .create()
+            .expect("Admin client creation error");
+        Self {
+            admin: Arc::new(admin),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn client(&self) -> &AdminClient<DefaultClientContext> {
+        &self.admin
+    }
+
+    /// Describes `topic`, or every topic in the cluster when `None`.
+    pub async fn describe_topics(&self, topic: Option<&str>) -> Result<Vec<TopicInfo>, AdminError> {
+        let admin = self.admin.clone();
+        let topic = topic.map(str::to_owned);
+        let timeout = self.timeout;
+        // Fetching metadata blocks until the broker answers.
+        let metadata = tokio::task::spawn_blocking(move || {
+            admin.inner().fetch_metadata(topic.as_deref(), timeout)
+        })
+        .await??;
+        Ok(metadata
+            .topics()
+            .iter()
+            .filter(|t| t.error().is_none())
+            .map(|t| TopicInfo {
+                name: t.name().to_owned(),
+                partitions: t
+                    .partitions()
+                    .iter()
+                    .map(|p| PartitionInfo {
+                        id: p.id(),
+                        leader: p.leader(),
+                        replicas: p.replicas().to_vec(),
+                        isr: p.isr().to_vec(),
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// Configs of `topic` that differ from the broker defaults.
+    pub async fn topic_configs(&self, topic: &str) -> Result<Vec<(String, String)>, AdminError> {
+        let opts = AdminOptions::new().request_timeout(Some(self.timeout));
+        let resource = ResourceSpecifier::Topic(topic);
+        let mut configs = Vec::new();
+        for result in self.admin.describe_configs([&resource], &opts).await? {
+            let config =
+                result.map_err(|code| AdminError::OperationError(topic.to_owned(), code))?;
+            configs.extend(
+                config
+                    .entries
+                    .into_iter()
+                    .filter(|entry| !entry.is_default)
+                    .filter_map(|entry| entry.value.map(|value| (entry.name, value))),
+            );
+        }
+        Ok(configs)
+    }
+
+    /// Lists the changes needed to bring the cluster in line with `definitions`,
+    /// without applying them.
+    pub async fn plan(
+        &self,
+        definitions: &[TopicDefinition],
+    ) -> Result<Vec<PlannedChange>, AdminError> {
+        let partitions: HashMap<String, i32> = self
+            .describe_topics(None)
+            .await?
+            .into_iter()
+            .map(|topic| (topic.name, topic.partitions.len() as i32))
+            .collect();
+        let mut configs = HashMap::new();
+        for definition in definitions {
+            let name = definition.name();
+            if partitions.contains_key(&name) {
+                let current = self.current_configs(&name).await?;
+                configs.insert(name, current);
+            }
+        }
+        Ok(plan_changes(definitions, &partitions, &configs))
+    }
+
+    /// Applies planned changes. Topics created concurrently by another instance are not an error.
+    pub async fn apply(&self, changes: &[PlannedChange]) -> Result<(), AdminError> {
+        let opts = AdminOptions::new().operation_timeout(Some(self.timeout));
+        for change in changes {
+            info!("Applying topic change: {}", change);
+            match change {
+                PlannedChange::CreateTopic {
+                    topic,
+                    partitions,
+                    replication_factor,
+                    configs,
+                } => {
+                    let new_topic = configs.iter().fold(
+                        NewTopic::new(
+                            topic,
+                            *partitions,
+                            TopicReplication::Fixed(*replication_factor),
+                        ),
+                        |new_topic, (k, v)| new_topic.set(k, v),
+                    );
+                    for result in self.admin.create_topics([&new_topic], &opts).await? {
+                        match result {
+                            Ok(_) | Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {}
+                            Err((topic, code)) => {
+                                return Err(AdminError::OperationError(topic, code))
+                            }
+                        }
+                    }
+                }
+                PlannedChange::IncreasePartitions { topic, to, .. } => {
+                    let new_partitions = NewPartitions::new(topic, *to as usize);
+                    for result in self
+                        .admin
+                        .create_partitions([&new_partitions], &opts)
+                        .await?
+                    {
+                        result.map_err(|(topic, code)| AdminError::OperationError(topic, code))?;
+                    }
+                }
+                PlannedChange::UpdateConfigs { topic, configs, .. } => {
+                    let alter_config = configs.iter().fold(
+                        AlterConfig::new(ResourceSpecifier::Topic(topic)),
+                        |alter_config, (k, v)| alter_config.set(k, v),
+                    );
+                    for result in self.admin.alter_configs([&alter_config], &opts).await? {
+                        result
+                            .map_err(|(_, code)| AdminError::OperationError(topic.clone(), code))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates or updates every topic in `definitions`. With `dry_run` the planned
+    /// changes are only logged.
+    pub async fn provision(
+        &self,
+        definitions: &[TopicDefinition],
+        dry_run: bool,
+    ) -> Result<Vec<PlannedChange>, AdminError> {
+        let changes = self.plan(definitions).await?;
+        if changes.is_empty() {
+            info!("All topics are up to date");
+        } else if dry_run {
+            for change in &changes {
+                info!("Planned topic change: {}", change);
+            }
+        } else {
+            self.apply(&changes).await?;
+        }
+        Ok(changes)
+    }
+
+    /// Every config of `topic`, defaults included.
+    async fn current_configs(
+        &self,
+        topic: &str,
+    ) -> Result<HashMap<String, Option<String>>, AdminError> {
+        let opts = AdminOptions::new().request_timeout(Some(self.timeout));
+        let resource = ResourceSpecifier::Topic(topic);
+        let mut configs = HashMap::new();
+        for result in self.admin.describe_configs([&resource], &opts).await? {
+            let config =
+                result.map_err(|code| AdminError::OperationError(topic.to_owned(), code))?;
+            configs.extend(
+                config
+                    .entries
+                    .into_iter()
+                    .map(|entry| (entry.name, entry.value)),
+            );
+        }
+        Ok(configs)
+    }
+}
+
+/// The changes that bring topics with `partitions` and `configs`, by name, in line
+/// with `definitions`. Topics missing from `partitions` don't exist yet.
+fn plan_changes(
+    definitions: &[TopicDefinition],
+    partitions: &HashMap<String, i32>,
+    configs: &HashMap<String, HashMap<String, Option<String>>>,
+) -> Vec<PlannedChange> {
+    let mut changes = Vec::new();
+    for definition in definitions {
+        let name = definition.name();
+        let desired_configs: Vec<(String, String)> = definition
+            .configs()
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v))
+            .collect();
+
+        let Some(&current_partitions) = partitions.get(&name) else {
+            changes.push(PlannedChange::CreateTopic {
+                topic: name,
+                partitions: definition.partitions,
+                replication_factor: definition.replication_factor,
+                configs: desired_configs,
+            });
+            continue;
+        };
+
+        if current_partitions < definition.partitions {
+            changes.push(PlannedChange::IncreasePartitions {
+                topic: name.clone(),
+                from: current_partitions,
+                to: definition.partitions,
+            });
+        } else if current_partitions > definition.partitions {
+            warn!(
+                "Topic {} has {} partitions, more than the {} defined; partitions cannot be removed",
+                name, current_partitions, definition.partitions
+            );
+        }
+
+        let current_configs = configs.get(&name);
+        let config_changes: Vec<ConfigChange> = desired_configs
+            .iter()
+            .filter_map(|(config, value)| {
+                let current = current_configs
+                    .and_then(|current| current.get(config))
+                    .cloned()
+                    .flatten();
+                (current.as_deref() != Some(value.as_str())).then(|| ConfigChange {
+                    name: config.clone(),
+                    current,
+                    desired: value.clone(),
+                })
+            })
+            .collect();
+        if !config_changes.is_empty() {
+            changes.push(PlannedChange::UpdateConfigs {
+                topic: name,
+                changes: config_changes,
+                configs: desired_configs,
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use common::events::{
+        constants::Topics,
+        topics::{CleanupPolicy, TopicDefinition},
+    };
+
+    use super::*;
+
+    fn definition(partitions: i32) -> TopicDefinition {
+        TopicDefinition {
+            topic: Topics::BookCreated,
+            partitions,
+            replication_factor: 1,
+            retention_ms: None,
+            cleanup_policy: CleanupPolicy::Compact,
+        }
+    }
+
+    fn configs(cleanup_policy: &str) -> HashMap<String, HashMap<String, Option<String>>> {
+        let current = HashMap::from([
+            ("retention.ms".to_owned(), Some("-1".to_owned())),
+            ("cleanup.policy".to_owned(), Some(cleanup_policy.to_owned())),
+        ]);
+        HashMap::from([(Topics::BookCreated.to_string(), current)])
+    }
+
+    #[test]
+    fn test_plan_creates_missing_topics() {
+        let changes = plan_changes(&[definition(3)], &HashMap::new(), &HashMap::new());
+        assert_eq!(
+            vec![PlannedChange::CreateTopic {
+                topic: Topics::BookCreated.to_string(),
+                partitions: 3,
+                replication_factor: 1,
+                configs: vec![
+                    ("retention.ms".to_owned(), "-1".to_owned()),
+                    ("cleanup.policy".to_owned(), "compact".to_owned()),
+                ],
+            }],
+            changes
+        );
+    }
+
+    #[test]
+    fn test_plan_leaves_up_to_date_topics() {
+        let partitions = HashMap::from([(Topics::BookCreated.to_string(), 3)]);
+        assert!(plan_changes(&[definition(3)], &partitions, &configs("compact")).is_empty());
+        // Partitions can't be removed, so more than defined is left alone.
+        assert!(plan_changes(&[definition(2)], &partitions, &configs("compact")).is_empty());
+    }
+
+    #[test]
+    fn test_plan_increases_partitions_and_updates_configs() {
+        let partitions = HashMap::from([(Topics::BookCreated.to_string(), 1)]);
+        let changes = plan_changes(&[definition(3)], &partitions, &configs("delete"));
+        assert_eq!(2, changes.len());
+        assert_eq!(
+            PlannedChange::IncreasePartitions {
+                topic: Topics::BookCreated.to_string(),
+                from: 1,
+                to: 3,
+            },
+            changes[0]
+        );
+        let PlannedChange::UpdateConfigs {
+            changes: config_changes,
+            configs,
+            ..
+        } = &changes[1]
+        else {
+            panic!("expected a config update");
+        };
+        assert_eq!(
+            vec![ConfigChange {
+                name: "cleanup.policy".to_owned(),
+                current: Some("delete".to_owned()),
+                desired: "compact".to_owned(),
+            }],
+            *config_changes
+        );
+        assert_eq!(2, configs.len());
+    }
+}
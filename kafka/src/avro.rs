@@ -0,0 +1,106 @@
+use apache_avro::{to_avro_datum, types::Value as AvroValue, AvroSchema, Schema};
+use schema_registry_converter::{
+    error::SRCError,
+    schema_registry_common::{get_subject, SubjectNameStrategy},
+};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+pub use common::events::topics::{SubjectStrategies, SubjectStrategy};
+
+/// Schema Registry names of a `SubjectStrategy`.
+pub trait SubjectNaming {
+    fn name_strategy<T: AvroSchema>(&self, topic: &str, is_key: bool) -> SubjectNameStrategy;
+
+    fn subject<T: AvroSchema>(&self, topic: &str, is_key: bool) -> Result<String, SRCError> {
+        get_subject(&self.name_strategy::<T>(topic, is_key))
+    }
+}
+
+impl SubjectNaming for SubjectStrategy {
+    fn name_strategy<T: AvroSchema>(&self, topic: &str, is_key: bool) -> SubjectNameStrategy {
+        name_strategy(*self, topic, &T::get_schema(), is_key)
+    }
+}
+
+/// The Schema Registry naming of `strategy` for a key or value with `schema`,
+/// for callers that only have the schema at runtime.
+pub fn name_strategy(
+    strategy: SubjectStrategy,
+    topic: &str,
+    schema: &Schema,
+    is_key: bool,
+) -> SubjectNameStrategy {
+    match strategy {
+        SubjectStrategy::Topic => SubjectNameStrategy::TopicNameStrategy(topic.to_owned(), is_key),
+        SubjectStrategy::Record => SubjectNameStrategy::RecordNameStrategy(record_name(schema)),
+        SubjectStrategy::TopicRecord => {
+            SubjectNameStrategy::TopicRecordNameStrategy(topic.to_owned(), record_name(schema))
+        }
+    }
+}
+
+/// Key of a produced record.
+pub enum MessageKey<K> {
+    /// Sent as raw UTF-8 bytes, without a schema.
+    Plain(String),
+    /// Encoded with Avro under the key subject of the topic.
+    Avro(K),
+}
+
+impl MessageKey<String> {
+    pub fn plain(key: impl Into<String>) -> Self {
+        MessageKey::Plain(key.into())
+    }
+}
+
+impl<K: Serialize + AvroSchema> MessageKey<K> {
+    pub fn avro(key: K) -> Self {
+        MessageKey::Avro(key)
+    }
+}
+
+/// Fully qualified name of a named schema, or the type name of a primitive one.
+pub fn record_name(schema: &Schema) -> String {
+    match serde_json::to_value(schema) {
+        Ok(JsonValue::Object(fields)) => {
+            let name = fields
+                .get("name")
+                .and_then(JsonValue::as_str)
+                .or_else(|| fields.get("type").and_then(JsonValue::as_str))
+                .unwrap_or_default();
+            match fields.get("namespace").and_then(JsonValue::as_str) {
+                Some(namespace) if !name.contains('.') => format!("{}.{}", namespace, name),
+                _ => name.to_owned(),
+            }
+        }
+        Ok(JsonValue::String(primitive)) => primitive,
+        _ => schema.canonical_form(),
+    }
+}
+
+/// Validates `json` against `schema` and encodes it in the Confluent wire format:
+/// a zero magic byte, the big-endian schema id, then the Avro datum.
+pub fn encode_json(
+    schema_id: u32,
+    schema: &Schema,
+    json: JsonValue,
+) -> Result<Vec<u8>, apache_avro::Error> {
+    let value = AvroValue::from(json).resolve(schema)?;
+    let mut bytes = vec![0];
+    bytes.extend_from_slice(&schema_id.to_be_bytes());
+    bytes.extend(to_avro_datum(schema, value)?);
+    Ok(bytes)
+}
+
+/// The schema id of a wire-format payload, or `None` if it isn't one.
+pub fn schema_id(payload: &[u8]) -> Option<u32> {
+    match payload {
+        [0, a, b, c, d, _rest @ ..] => Some(u32::from_be_bytes([*a, *b, *c, *d])),
+        _ => None,
+    }
+}
+
+pub fn to_json(value: AvroValue) -> Result<JsonValue, apache_avro::Error> {
+    JsonValue::try_from(value)
+}
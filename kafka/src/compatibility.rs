@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use apache_avro::{AvroSchema, Schema};
+use common::events::{
+    compatibility::CompatibilityLevel,
+    topics::{KeyFormat, Topic},
+};
+use reqwest::{Client, StatusCode};
+use schema_registry_converter::error::SRCError;
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+use tracing::info;
+
+use crate::avro::{SubjectNaming, SubjectStrategy};
+
+const SCHEMA_REGISTRY_CONTENT_TYPE: &str = "application/vnd.schemaregistry.v1+json";
+
+#[derive(Error, Debug)]
+pub enum CompatibilityError {
+    #[error("Schema registry request error")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("Subject name error")]
+    SubjectError(#[from] SRCError),
+
+    #[error("Schema serialization error")]
+    SerializationError(#[from] serde_json::Error),
+
+    #[error("Schema registry returned {status} for {subject}: {body}")]
+    RegistryError {
+        subject: String,
+        status: StatusCode,
+        body: String,
+    },
+
+    #[error("{subject} is checked at {actual} by the registry, which doesn't enforce {required}")]
+    WeakerLevel {
+        subject: String,
+        required: CompatibilityLevel,
+        actual: CompatibilityLevel,
+    },
+
+    #[error("Schema for {subject} is not {level} compatible: {messages:?}")]
+    Incompatible {
+        subject: String,
+        level: CompatibilityLevel,
+        messages: Vec<String>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigResponse {
+    compatibility_level: String,
+}
+
+#[derive(Deserialize)]
+struct CompatibilityResponse {
+    is_compatible: bool,
+    #[serde(default)]
+    messages: Vec<String>,
+}
+
+/// Checks schemas against the Schema Registry before a service starts producing.
+///
+/// The registry checks at the level configured for each subject there, so the gate
+/// fails when that level doesn't enforce the one required here. Changing it is left
+/// to `set_level`, which the gate never calls itself.
+pub struct CompatibilityGate {
+    schema_registry_url: String,
+    default_level: CompatibilityLevel,
+    subject_levels: HashMap<String, CompatibilityLevel>,
+    client: Client,
+}
+
+impl CompatibilityGate {
+    pub fn new(schema_registry_url: String, default_level: CompatibilityLevel) -> Self {
+        Self {
+            schema_registry_url: schema_registry_url.trim_end_matches('/').to_owned(),
+            default_level,
+            subject_levels: HashMap::new(),
+            client: Client::new(),
+        }
+    }
+
+    pub fn with_subject_level(mut self, subject: String, level: CompatibilityLevel) -> Self {
+        self.subject_levels.insert(subject, level);
+        self
+    }
+
+    pub fn level_for(&self, subject: &str) -> CompatibilityLevel {
+        self.subject_levels
+            .get(subject)
+            .copied()
+            .unwrap_or(self.default_level)
+    }
+
+    pub async fn check<T: AvroSchema>(
+        &self,
+        topic: &str,
+        strategy: SubjectStrategy,
+        is_key: bool,
+    ) -> Result<(), CompatibilityError> {
+        let subject = strategy.subject::<T>(topic, is_key)?;
+        let level = self.level_for(&subject);
+        let actual = self.registry_level(&subject).await?;
+        if !actual.satisfies(level) {
+            return Err(CompatibilityError::WeakerLevel {
+                subject,
+                required: level,
+                actual,
+            });
+        }
+
+        let (url, body) = self.compatibility_request(&subject, level, &T::get_schema())?;
+        let response = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, SCHEMA_REGISTRY_CONTENT_TYPE)
+            .body(body)
+            .send()
+            .await?;
+
+        match response.status() {
+            // Nothing registered yet, so there is nothing to be incompatible with.
+            StatusCode::NOT_FOUND => {
+                info!(
+                    "No schema registered for {}, skipping compatibility check",
+                    subject
+                );
+                Ok(())
+            }
+            status if status.is_success() => {
+                let body: CompatibilityResponse = serde_json::from_str(&response.text().await?)
+                    .map_err(|e| CompatibilityError::RegistryError {
+                        subject: subject.clone(),
+                        status,
+                        body: e.to_string(),
+                    })?;
+                if body.is_compatible {
+                    info!("Schema for {} is {} compatible", subject, level);
+                    Ok(())
+                } else {
+                    Err(CompatibilityError::Incompatible {
+                        subject,
+                        level,
+                        messages: body.messages,
+                    })
+                }
+            }
+            status => Err(CompatibilityError::RegistryError {
+                subject,
+                status,
+                body: response.text().await?,
+            }),
+        }
+    }
+
+    /// The URL and body of the request checking `schema` for `subject` at `level`.
+    ///
+    /// The schema goes in full, since its Parsing Canonical Form drops the defaults
+    /// that make added fields compatible. Transitive levels are checked against every
+    /// registered version, the others against the latest only.
+    fn compatibility_request(
+        &self,
+        subject: &str,
+        level: CompatibilityLevel,
+        schema: &Schema,
+    ) -> Result<(String, String), CompatibilityError> {
+        let versions = if level.is_transitive() {
+            "versions"
+        } else {
+            "versions/latest"
+        };
+        let url = format!(
+            "{}/compatibility/subjects/{}/{}?verbose=true",
+            self.schema_registry_url, subject, versions
+        );
+        let body = json!({ "schema": serde_json::to_string(schema)? }).to_string();
+        Ok((url, body))
+    }
+
+    /// Checks the value schema of `T`, and its key schema when keys are Avro-encoded.
+    pub async fn check_topic<T: Topic>(&self) -> Result<(), CompatibilityError> {
+        let topic = T::name();
+        self.check::<T::Payload>(&topic, T::SUBJECT_STRATEGIES.value, false)
+            .await?;
+        if T::KEY_FORMAT == KeyFormat::Avro {
+            self.check::<T::Key>(&topic, T::SUBJECT_STRATEGIES.key, true)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The level the registry enforces on `subject`, its own or else the global one.
+    pub async fn registry_level(
+        &self,
+        subject: &str,
+    ) -> Result<CompatibilityLevel, CompatibilityError> {
+        let response = self
+            .client
+            .get(format!(
+                "{}/config/{}?defaultToGlobal=true",
+                self.schema_registry_url, subject
+            ))
+            .send()
+            .await?;
+        // Registries without `defaultToGlobal` answer 404 for subjects with no level.
+        let response = if response.status() == StatusCode::NOT_FOUND {
+            self.client
+                .get(format!("{}/config", self.schema_registry_url))
+                .send()
+                .await?
+        } else {
+            response
+        };
+        let status = response.status();
+        let body = response.text().await?;
+        let registry_error = |body: String| CompatibilityError::RegistryError {
+            subject: subject.to_owned(),
+            status,
+            body,
+        };
+        if !status.is_success() {
+            return Err(registry_error(body));
+        }
+        let config: ConfigResponse =
+            serde_json::from_str(&body).map_err(|e| registry_error(e.to_string()))?;
+        config
+            .compatibility_level
+            .parse()
+            .map_err(|_| registry_error(body))
+    }
+
+    /// Sets the level the registry enforces on `subject`.
+    pub async fn set_level(
+        &self,
+        subject: &str,
+        level: CompatibilityLevel,
+    ) -> Result<(), CompatibilityError> {
+        let response = self
+            .client
+            .put(format!("{}/config/{}", self.schema_registry_url, subject))
+            .header(reqwest::header::CONTENT_TYPE, SCHEMA_REGISTRY_CONTENT_TYPE)
+            .body(json!({ "compatibility": level.to_string() }).to_string())
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(CompatibilityError::RegistryError {
+                subject: subject.to_owned(),
+                status: response.status(),
+                body: response.text().await?,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::events::compatibility::check_compatibility;
+
+    use super::*;
+
+    fn gate() -> CompatibilityGate {
+        CompatibilityGate::new(
+            "http://registry:8081/".to_owned(),
+            CompatibilityLevel::Backward,
+        )
+    }
+
+    fn book(fields: &str) -> Schema {
+        Schema::parse_str(&format!(
+            r#"{{"type": "record", "name": "Book", "fields": [{}]}}"#,
+            fields
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_added_field_with_default_passes() {
+        let old = book(r#"{"name": "title", "type": "string"}"#);
+        let new = book(
+            r#"{"name": "title", "type": "string"},
+               {"name": "language", "type": ["null", "string"], "default": null}"#,
+        );
+        let (_, body) = gate()
+            .compatibility_request("books-value", CompatibilityLevel::Backward, &new)
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let sent = Schema::parse_str(body["schema"].as_str().unwrap()).unwrap();
+        assert_eq!(
+            Ok(()),
+            check_compatibility(&sent, &old, CompatibilityLevel::Backward)
+        );
+    }
+
+    #[test]
+    fn test_transitive_levels_check_every_version() {
+        let schema = book(r#"{"name": "title", "type": "string"}"#);
+        let (latest, _) = gate()
+            .compatibility_request("books-value", CompatibilityLevel::Full, &schema)
+            .unwrap();
+        assert_eq!(
+            "http://registry:8081/compatibility/subjects/books-value/versions/latest?verbose=true",
+            latest
+        );
+        let (all, _) = gate()
+            .compatibility_request("books-value", CompatibilityLevel::FullTransitive, &schema)
+            .unwrap();
+        assert_eq!(
+            "http://registry:8081/compatibility/subjects/books-value/versions?verbose=true",
+            all
+        );
+    }
+}
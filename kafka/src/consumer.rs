@@ -1,5 +1,11 @@
-use crate::{commons::create_schema_registry_settings, utils::HeaderExtractor};
+use crate::{
+    commons::create_schema_registry_settings,
+    offsets::{self, OffsetSpec},
+    rebalance::{RebalanceContext, RebalanceOptions},
+    utils::HeaderExtractor,
+};
 use apache_avro::from_value;
+use common::events::topics::Topic;
 use dotenv::dotenv;
 use opentelemetry::{
     global,
@@ -8,21 +14,230 @@ use opentelemetry::{
 };
 use rdkafka::{
     config::RDKafkaLogLevel,
-    consumer::{CommitMode, Consumer, StreamConsumer},
-    ClientConfig, Message,
+    consumer::{CommitMode, Consumer, ConsumerContext, StreamConsumer},
+    error::{KafkaError, KafkaResult},
+    message::BorrowedMessage,
+    ClientConfig, Message, Offset, TopicPartitionList,
 };
-use schema_registry_converter::async_impl::{
-    easy_avro::EasyAvroDecoder, schema_registry::SrSettings,
+use schema_registry_converter::{
+    async_impl::easy_avro::EasyAvroDecoder, avro_common::DecodeResult,
 };
 use serde::Deserialize;
-use std::{env, fmt::Debug};
+use std::{collections::HashMap, fmt::Debug, sync::Arc, time::Duration};
 use tokio::sync::mpsc::UnboundedSender;
 use tracing::{error, info};
 
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) fn consumer_config(bootstrap_servers: String, group_id: String) -> ClientConfig {
+    // let api_key = env::var("KAFKA_API_KEY").expect("kafka key not found in variables");
+    // let api_password =
+    //     env::var("KAFKA_API_SECRET").expect("kafka secrets not found in variables");
+    let mut config = ClientConfig::new();
+    config
+        .set("group.id", group_id)
+        .set("bootstrap.servers", bootstrap_servers)
+        .set("session.timeout.ms", "6000")
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .set("allow.auto.create.topics", "false")
+        // .set("sasl.username", api_key)
+        // .set("sasl.password", api_password)
+        .set_log_level(RDKafkaLogLevel::Debug);
+    config
+}
+
+/// The trace context propagated in the headers of `msg`.
+pub(crate) fn message_context(msg: &BorrowedMessage<'_>) -> Context {
+    if let Some(headers) = msg.headers() {
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+    } else {
+        Context::current()
+    }
+}
+
+/// Where a record was read from, and its timestamp in epoch milliseconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordMetadata {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub timestamp: Option<i64>,
+}
+
+impl RecordMetadata {
+    pub(crate) fn of(msg: &BorrowedMessage<'_>) -> Self {
+        Self {
+            topic: msg.topic().to_owned(),
+            partition: msg.partition(),
+            offset: msg.offset(),
+            timestamp: msg.timestamp().to_millis(),
+        }
+    }
+
+    /// `topic/partition/offset`, unique per record but not per event if it was produced twice.
+    pub fn event_id(&self) -> String {
+        format!("{}/{}/{}", self.topic, self.partition, self.offset)
+    }
+}
+
+/// A record of a keyed topic: a new payload for its key, or a tombstone (a record
+/// without a value) telling compacted topics to drop the key.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change<T> {
+    Upsert(T),
+    /// The record's key, as UTF-8.
+    Tombstone(String),
+}
+
+/// The high watermark of each partition a bounded read still has to reach.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct EndOffsets(HashMap<i32, i64>);
+
+impl EndOffsets {
+    /// A record was read; offsets can skip past the end when the last ones were
+    /// compacted away.
+    fn record(&mut self, partition: i32, offset: i64) {
+        if self.0.get(&partition).is_some_and(|end| offset + 1 >= *end) {
+            self.0.remove(&partition);
+        }
+    }
+
+    /// The consumer caught up with `partition`. This also covers partitions ending in
+    /// transaction markers, whose offsets never come back as records.
+    fn reached_end(&mut self, partition: i32) {
+        self.0.remove(&partition);
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Where consumption starts on each partition.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum StartPosition {
+    /// The group's committed offsets, or the earliest offset when none is committed.
+    #[default]
+    Committed,
+    Earliest,
+    Latest,
+    /// Specific offsets by partition; unlisted partitions start from committed offsets.
+    Offsets(HashMap<i32, i64>),
+    /// The first offset at or after these epoch milliseconds, via `offsets_for_times`.
+    Timestamp(i64),
+}
+
 pub struct KafkaConsumer {
-    consumer: StreamConsumer,
-    avro_decoder: EasyAvroDecoder,
+    pub(crate) consumer: Arc<StreamConsumer<RebalanceContext>>,
+    pub(crate) avro_decoder: EasyAvroDecoder,
+    pub(crate) group_id: String,
     topic: String,
+    start_position: StartPosition,
+    assignment: Option<Vec<i32>>,
+    all_partitions: bool,
+    bounded: bool,
+    manual_commit: bool,
+}
+
+/// Commits offsets on behalf of a `KafkaConsumer` in manual commit mode.
+#[derive(Clone)]
+pub struct OffsetCommitter {
+    consumer: Arc<StreamConsumer<RebalanceContext>>,
+}
+
+impl OffsetCommitter {
+    /// Synchronously commits the next offset to read of each partition of `topic`.
+    pub fn commit(&self, topic: &str, offsets: &HashMap<i32, i64>) -> KafkaResult<()> {
+        let mut tpl = TopicPartitionList::new();
+        for (partition, offset) in offsets {
+            tpl.add_partition_offset(topic, *partition, Offset::Offset(*offset))?;
+        }
+        self.consumer.commit(&tpl, CommitMode::Sync)
+    }
+}
+
+/// The next record, or `None` once a bounded read reached every end offset.
+/// End-of-partition events are handled here and never returned.
+async fn next_message<'a, C: ConsumerContext + 'static>(
+    consumer: &'a StreamConsumer<C>,
+    end_offsets: &mut Option<EndOffsets>,
+) -> Option<KafkaResult<BorrowedMessage<'a>>> {
+    loop {
+        if end_offsets.as_ref().is_some_and(EndOffsets::is_done) {
+            return None;
+        }
+        match consumer.recv().await {
+            Err(KafkaError::PartitionEOF(partition)) => {
+                if let Some(end_offsets) = end_offsets.as_mut() {
+                    end_offsets.reached_end(partition);
+                }
+            }
+            Ok(msg) => {
+                if let Some(end_offsets) = end_offsets.as_mut() {
+                    end_offsets.record(msg.partition(), msg.offset());
+                }
+                return Some(Ok(msg));
+            }
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+/// The key of `msg`, as UTF-8, when it is a tombstone.
+fn tombstone_key<M: Message>(msg: &M) -> Option<String> {
+    msg.payload()
+        .is_none()
+        .then(|| String::from_utf8_lossy(msg.key().unwrap_or_default()).into_owned())
+}
+
+/// `change` with the value of an upsert deserialized into `T`.
+fn deserialize_change<T: for<'a> Deserialize<'a>>(
+    change: Change<DecodeResult>,
+) -> Result<Change<T>, apache_avro::Error> {
+    Ok(match change {
+        Change::Upsert(decoded) => Change::Upsert(from_value::<T>(&decoded.value)?),
+        Change::Tombstone(key) => Change::Tombstone(key),
+    })
+}
+
+/// Reads records until a bounded read is done or the consumer fails, handing each
+/// one, decoded or as a tombstone, to `handle` inside a tracing span. Records that
+/// can't be decoded are logged and skipped. Each record is committed once handled
+/// unless `manual_commit`.
+pub(crate) async fn consume_records<C: ConsumerContext + 'static>(
+    consumer: &StreamConsumer<C>,
+    decoder: &EasyAvroDecoder,
+    mut end_offsets: Option<EndOffsets>,
+    manual_commit: bool,
+    mut handle: impl FnMut(&BorrowedMessage<'_>, Change<DecodeResult>),
+) -> KafkaResult<()> {
+    while let Some(result) = next_message(consumer, &mut end_offsets).await {
+        let msg = result?;
+        let mut span = global::tracer("consumer")
+            .start_with_context("consume_payload", &message_context(&msg));
+
+        if let Some(key) = tombstone_key(&msg) {
+            info!(
+                "tombstone for key '{}', topic: {}, partition: {}, offset: {}",
+                key,
+                msg.topic(),
+                msg.partition(),
+                msg.offset()
+            );
+            handle(&msg, Change::Tombstone(key));
+        } else {
+            match decoder.decode(msg.payload()).await {
+                Ok(decoded) => handle(&msg, Change::Upsert(decoded)),
+                Err(e) => error!("Error getting value {}", e),
+            }
+        }
+        if !manual_commit {
+            consumer.commit_message(&msg, CommitMode::Async)?;
+        }
+        span.end();
+    }
+    Ok(())
 }
 
 impl KafkaConsumer {
@@ -31,29 +246,93 @@ impl KafkaConsumer {
         group_id: String,
         topic: String,
         schema_registry_url: String,
+    ) -> Self {
+        Self::with_rebalance(
+            bootstrap_servers,
+            group_id,
+            topic,
+            schema_registry_url,
+            RebalanceOptions::default(),
+        )
+    }
+
+    pub fn with_rebalance(
+        bootstrap_servers: String,
+        group_id: String,
+        topic: String,
+        schema_registry_url: String,
+        rebalance: RebalanceOptions,
     ) -> Self {
         dotenv().ok();
-        // let api_key = env::var("KAFKA_API_KEY").expect("kafka key not found in variables");
-        // let api_password =
-        //     env::var("KAFKA_API_SECRET").expect("kafka secrets not found in variables");
-        let consumer: StreamConsumer = ClientConfig::new()
-            .set("group.id", group_id)
-            .set("bootstrap.servers", bootstrap_servers)
-            .set("session.timeout.ms", "6000")
-            .set("enable.auto.commit", "false")
-            .set("auto.offset.reset", "earliest")
-            .set("allow.auto.create.topics", "true")
-            // .set("sasl.username", api_key)
-            // .set("sasl.password", api_password)
-            .set_log_level(RDKafkaLogLevel::Debug)
-            .create()
-            .expect("Consumer creation error");
+        let mut config = consumer_config(bootstrap_servers, group_id.clone());
+        // Lets bounded reads tell when a partition is exhausted.
+        config.set("enable.partition.eof", "true");
+        if rebalance.cooperative_sticky {
+            config.set("partition.assignment.strategy", "cooperative-sticky");
+        }
+        let consumer: Arc<StreamConsumer<RebalanceContext>> = Arc::new(
+            config
+                .create_with_context(RebalanceContext::new(rebalance.listener))
+                .expect("Consumer creation error"),
+        );
+        consumer.context().attach(&consumer);
         let sr_settings = create_schema_registry_settings(schema_registry_url);
         let avro_decoder = EasyAvroDecoder::new(sr_settings);
         Self {
             consumer,
+            group_id,
             topic,
             avro_decoder,
+            start_position: StartPosition::default(),
+            assignment: None,
+            all_partitions: false,
+            bounded: false,
+            manual_commit: false,
+        }
+    }
+
+    /// Any position other than `Committed` applies to each partition the first time
+    /// the group assigns it to this consumer; partitions it gets later on resume
+    /// from the group's committed offsets.
+    pub fn with_start_position(mut self, start_position: StartPosition) -> Self {
+        self.start_position = start_position;
+        self
+    }
+
+    /// Reads only these partitions, bypassing group management. Offsets are
+    /// still committed under the group id.
+    pub fn with_assignment(mut self, partitions: Vec<i32>) -> Self {
+        self.assignment = Some(partitions);
+        self
+    }
+
+    /// Reads every partition of the topic, bypassing group management, for consumers
+    /// whose state has to cover the whole topic. Offsets are still committed under
+    /// the group id.
+    pub fn with_all_partitions(mut self) -> Self {
+        self.all_partitions = true;
+        self
+    }
+
+    /// Stops once every partition has been read up to the high watermark it had
+    /// when consumption started, or to its end when the last offsets hold no
+    /// records, for batch reprocessing.
+    pub fn bounded(mut self) -> Self {
+        self.bounded = true;
+        self
+    }
+
+    /// Leaves committing to the caller, through `committer`, instead of committing
+    /// each record once it is handed on. Offsets are no longer committed on revoke.
+    pub fn with_manual_commit(mut self) -> Self {
+        self.manual_commit = true;
+        self.consumer.context().set_commit_on_revoke(false);
+        self
+    }
+
+    pub fn committer(&self) -> OffsetCommitter {
+        OffsetCommitter {
+            consumer: self.consumer.clone(),
         }
     }
 
@@ -61,54 +340,317 @@ impl KafkaConsumer {
         &self,
         sender: UnboundedSender<T>,
     ) {
-        self.consumer
-            .subscribe(&[&self.topic])
-            .expect("Can't subscribe to topics");
-
-        while let Ok(msg) = self.consumer.recv().await {
-            let context = if let Some(headers) = msg.headers() {
-                global::get_text_map_propagator(|propagator| {
-                    propagator.extract(&HeaderExtractor(&headers))
-                })
-            } else {
-                Context::current()
-            };
+        self.consume_topic(&self.topic, |_, change| match change {
+            Change::Upsert(payload) => sender.send(payload).map_err(|e| e.to_string()),
+            Change::Tombstone(_) => Ok(()),
+        })
+        .await
+    }
 
-            let mut span =
-                global::tracer("consumer").start_with_context("consume_payload", &context);
+    /// Consumes the topic registered for `T`, decoding records into its payload type.
+    pub async fn subscribe<T: Topic>(&self, sender: UnboundedSender<T::Payload>) {
+        self.consume_topic(&T::name(), |_, change| match change {
+            Change::Upsert(payload) => sender.send(payload).map_err(|e| e.to_string()),
+            Change::Tombstone(_) => Ok(()),
+        })
+        .await
+    }
 
-            let value_result = match self.avro_decoder.decode(msg.payload()).await {
-                Ok(r) => Ok(r),
-                Err(e) => {
-                    error!("Error getting value {}", e);
-                    Err(e)
-                }
-            };
+    /// Like `subscribe`, also sending where each record was read from and its timestamp.
+    pub async fn subscribe_with_metadata<T: Topic>(
+        &self,
+        sender: UnboundedSender<(RecordMetadata, T::Payload)>,
+    ) {
+        self.consume_topic(&T::name(), |metadata, change| match change {
+            Change::Upsert(payload) => sender.send((metadata, payload)).map_err(|e| e.to_string()),
+            Change::Tombstone(_) => Ok(()),
+        })
+        .await
+    }
 
-            if let Ok(value) = value_result {
-                if let Ok(deserialized_payload) = from_value::<T>(&value.value) {
-                    info!(  "key: '{:?}', payload: '{:?}', topic: {}, partition: {}, offset: {}, timestamp: {:?}",
-                                    msg.key(),
-                                    deserialized_payload,
-                                    msg.topic(),
-                                    msg.partition(),
-                                    msg.offset(),
-                                    msg.timestamp());
-                    if let Err(e) = sender.send(deserialized_payload) {
-                        error!("Error while sending via channel: {}", e);
-                    } else {
-                        info!("Message consumed successfully");
+    /// Like `subscribe_with_metadata`, also sending tombstones, which the others skip.
+    pub async fn subscribe_changes<T: Topic>(
+        &self,
+        sender: UnboundedSender<(RecordMetadata, Change<T::Payload>)>,
+    ) {
+        self.consume_topic(&T::name(), |metadata, change| {
+            sender.send((metadata, change)).map_err(|e| e.to_string())
+        })
+        .await
+    }
+
+    async fn consume_topic<T: Clone + Debug + for<'a> Deserialize<'a>>(
+        &self,
+        topic: &str,
+        send: impl Fn(RecordMetadata, Change<T>) -> Result<(), String>,
+    ) {
+        let end_offsets = self.start(topic).await.expect("Can't subscribe to topics");
+        if end_offsets.as_ref().is_some_and(EndOffsets::is_done) {
+            info!("Nothing to read from {}", topic);
+            return;
+        }
+
+        let result = consume_records(
+            self.consumer.as_ref(),
+            &self.avro_decoder,
+            end_offsets,
+            self.manual_commit,
+            |msg, change| {
+                let change = match deserialize_change::<T>(change) {
+                    Ok(change) => {
+                        if let Change::Upsert(payload) = &change {
+                            info!(
+                                "key: '{:?}', payload: '{:?}', topic: {}, partition: {}, offset: {}, timestamp: {:?}",
+                                msg.key(),
+                                payload,
+                                msg.topic(),
+                                msg.partition(),
+                                msg.offset(),
+                                msg.timestamp()
+                            );
+                        }
+                        Some(change)
+                    }
+                    Err(_) => {
+                        error!("Error while deserializing message payload");
+                        None
+                    }
+                };
+                if let Some(change) = change {
+                    match send(RecordMetadata::of(msg), change) {
+                        Ok(()) => info!("Message consumed successfully"),
+                        Err(e) => error!("Error while sending via channel: {}", e),
                     }
-                } else {
-                    error!("Error while deserializing message payload");
                 }
-            } else {
-                error!("Error while deserializing message payload");
+                self.consumer.context().mark_processed(
+                    msg.topic(),
+                    msg.partition(),
+                    msg.offset(),
+                );
+            },
+        )
+        .await;
+        match result {
+            Ok(()) => info!("Read {} up to its end offsets", topic),
+            Err(e) => error!("Error consuming {}: {}", topic, e),
+        }
+    }
+
+    /// The next record, or `None` once a bounded read reached every end offset.
+    pub(crate) async fn next_message(
+        &self,
+        end_offsets: &mut Option<EndOffsets>,
+    ) -> Option<KafkaResult<BorrowedMessage<'_>>> {
+        next_message(self.consumer.as_ref(), end_offsets).await
+    }
+
+    /// Subscribes or assigns partitions, returning the offsets to stop at when bounded.
+    pub(crate) async fn start(&self, topic: &str) -> KafkaResult<Option<EndOffsets>> {
+        if self.assignment.is_none() && !self.all_partitions && !self.bounded {
+            if self.start_position != StartPosition::Committed {
+                let start_offsets = self.start_offsets(topic, None).await?;
+                info!(
+                    "Starting assigned partitions of {} from {:?}",
+                    topic, start_offsets
+                );
+                self.consumer.context().set_start_offsets(&start_offsets);
             }
-            self.consumer
-                .commit_message(&msg, CommitMode::Async)
-                .unwrap();
-            span.end();
+            self.consumer.subscribe(&[topic])?;
+            return Ok(None);
         }
+
+        let start_offsets = self.start_offsets(topic, self.assignment.clone()).await?;
+        info!("Assigning {:?} of {}", start_offsets, topic);
+        self.consumer.assign(&start_offsets)?;
+        if !self.bounded {
+            return Ok(None);
+        }
+
+        let topic = topic.to_owned();
+        self.blocking(move |consumer| end_offsets(consumer, &topic, &start_offsets))
+            .await
+            .map(Some)
+    }
+
+    /// The offsets to start `topic` from on `partitions`, or on all of its partitions
+    /// when `None`.
+    async fn start_offsets(
+        &self,
+        topic: &str,
+        partitions: Option<Vec<i32>>,
+    ) -> KafkaResult<TopicPartitionList> {
+        let topic = topic.to_owned();
+        let start_position = self.start_position.clone();
+        self.blocking(move |consumer| {
+            let partitions = match partitions {
+                Some(partitions) => partitions,
+                None => offsets::partitions(consumer, &topic, METADATA_TIMEOUT)?,
+            };
+            start_offsets(consumer, &start_position, &topic, &partitions)
+        })
+        .await
+    }
+
+    /// Runs `f` on the consumer off the async runtime, since offset and metadata
+    /// lookups block until the broker answers.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&StreamConsumer<RebalanceContext>) -> KafkaResult<T> + Send + 'static,
+    ) -> KafkaResult<T> {
+        let consumer = self.consumer.clone();
+        match tokio::task::spawn_blocking(move || f(&consumer)).await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+fn start_offsets(
+    consumer: &StreamConsumer<RebalanceContext>,
+    start_position: &StartPosition,
+    topic: &str,
+    partitions: &[i32],
+) -> KafkaResult<TopicPartitionList> {
+    let spec = match start_position {
+        StartPosition::Earliest => OffsetSpec::Earliest,
+        StartPosition::Latest => OffsetSpec::Latest,
+        StartPosition::Timestamp(timestamp) => OffsetSpec::Timestamp(*timestamp),
+        StartPosition::Committed | StartPosition::Offsets(_) => {
+            let mut tpl = TopicPartitionList::new();
+            for partition in partitions {
+                let offset = match start_position {
+                    StartPosition::Offsets(offsets) => offsets
+                        .get(partition)
+                        .map_or(Offset::Stored, |o| Offset::Offset(*o)),
+                    _ => Offset::Stored,
+                };
+                tpl.add_partition_offset(topic, *partition, offset)?;
+            }
+            return Ok(tpl);
+        }
+    };
+    offsets::resolve_offsets(consumer, topic, partitions, spec, METADATA_TIMEOUT)
+}
+
+/// The high watermarks a bounded read of `start_offsets` stops at, leaving out the
+/// partitions it starts at the end of.
+fn end_offsets(
+    consumer: &StreamConsumer<RebalanceContext>,
+    topic: &str,
+    start_offsets: &TopicPartitionList,
+) -> KafkaResult<EndOffsets> {
+    let committed = consumer.committed_offsets(start_offsets.clone(), METADATA_TIMEOUT)?;
+    let mut end_offsets = HashMap::new();
+    for elem in start_offsets.elements() {
+        let (low, high) = consumer.fetch_watermarks(topic, elem.partition(), METADATA_TIMEOUT)?;
+        let start = match elem.offset() {
+            Offset::Offset(offset) => offset,
+            _ => match committed
+                .find_partition(topic, elem.partition())
+                .map(|c| c.offset())
+            {
+                Some(Offset::Offset(offset)) => offset,
+                _ => low,
+            },
+        };
+        if start < high {
+            end_offsets.insert(elem.partition(), high);
+        }
+    }
+    Ok(EndOffsets(end_offsets))
+}
+
+#[cfg(test)]
+mod tests {
+    use apache_avro::to_value;
+    use common::events::dto::{AlertStatus, AnalyticsAlert};
+    use rdkafka::{message::OwnedMessage, Timestamp};
+
+    use super::*;
+
+    fn message(key: &str, payload: Option<&[u8]>) -> OwnedMessage {
+        OwnedMessage::new(
+            payload.map(<[u8]>::to_vec),
+            Some(key.as_bytes().to_vec()),
+            "books".to_owned(),
+            Timestamp::NotAvailable,
+            0,
+            7,
+            None,
+        )
+    }
+
+    fn end_offsets(offsets: &[(i32, i64)]) -> EndOffsets {
+        EndOffsets(offsets.iter().copied().collect())
+    }
+
+    #[test]
+    fn test_end_offsets_done_at_last_record() {
+        let mut end_offsets = end_offsets(&[(0, 3), (1, 1)]);
+        end_offsets.record(0, 1);
+        end_offsets.record(1, 0);
+        assert_eq!(self::end_offsets(&[(0, 3)]), end_offsets);
+        end_offsets.record(0, 2);
+        assert!(end_offsets.is_done());
+    }
+
+    #[test]
+    fn test_end_offsets_skip_compacted_offsets() {
+        let mut end_offsets = end_offsets(&[(0, 10)]);
+        end_offsets.record(0, 4);
+        assert!(!end_offsets.is_done());
+        // Offsets 5 to 9 were compacted away, a record produced since is read next.
+        end_offsets.record(0, 12);
+        assert!(end_offsets.is_done());
+    }
+
+    #[test]
+    fn test_end_offsets_done_at_partition_eof() {
+        // Offset 9 is a transaction marker, so no record ever reaches it.
+        let mut end_offsets = end_offsets(&[(0, 10), (1, 5)]);
+        end_offsets.record(0, 8);
+        end_offsets.reached_end(0);
+        assert_eq!(self::end_offsets(&[(1, 5)]), end_offsets);
+        end_offsets.reached_end(2);
+        end_offsets.reached_end(1);
+        assert!(end_offsets.is_done());
+    }
+
+    #[test]
+    fn test_tombstones_carry_their_key() {
+        assert_eq!(Some("42".to_owned()), tombstone_key(&message("42", None)));
+        assert_eq!(None, tombstone_key(&message("42", Some(b"\0"))));
+    }
+
+    #[test]
+    fn test_deserialize_change() {
+        let tombstone = deserialize_change::<AnalyticsAlert>(Change::Tombstone("42".to_owned()));
+        assert_eq!(Change::Tombstone("42".to_owned()), tombstone.unwrap());
+
+        let alert = AnalyticsAlert {
+            rule: "spike".to_owned(),
+            status: AlertStatus::Firing,
+            severity: "warning".to_owned(),
+            message: "Too many books".to_owned(),
+            value: 12.0,
+            threshold: 10.0,
+            window_start: 0,
+            window_end: 60_000,
+            raised_at: 60_000,
+        };
+        let decoded = DecodeResult {
+            name: None,
+            value: to_value(&alert).unwrap(),
+        };
+        assert_eq!(
+            Change::Upsert(alert),
+            deserialize_change(Change::Upsert(decoded)).unwrap()
+        );
+
+        let invalid = DecodeResult {
+            name: None,
+            value: to_value("not an alert").unwrap(),
+        };
+        assert!(deserialize_change::<AnalyticsAlert>(Change::Upsert(invalid)).is_err());
     }
 }
@@ -0,0 +1,149 @@
+use apache_avro::from_value;
+use common::events::topics::Topic;
+use database::processed_events::{dead_letter, mark_processed};
+use futures::future::BoxFuture;
+use opentelemetry::{
+    global,
+    trace::{Span, Tracer},
+};
+use rdkafka::{
+    consumer::{CommitMode, Consumer},
+    error::KafkaError,
+    Message,
+};
+use schema_registry_converter::error::SRCError;
+use sea_orm::{DatabaseConnection, DatabaseTransaction, DbErr, TransactionTrait};
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::consumer::{message_context, EndOffsets, KafkaConsumer, RecordMetadata};
+
+#[derive(Error, Debug)]
+pub enum IdempotentError {
+    #[error("Kafka error")]
+    KafkaError(#[from] KafkaError),
+
+    #[error("Database error")]
+    DatabaseError(#[from] DbErr),
+
+    #[error("Decoding error")]
+    DecodeError(#[from] SRCError),
+}
+
+/// Whether decoding failed on the record itself, so a retry fails the same way,
+/// rather than on fetching its schema from the registry.
+fn is_undecodable(e: &SRCError) -> bool {
+    e.error.starts_with("Invalid bytes") || e.error == "Could not transform bytes using schema"
+}
+
+/// Handles a record inside the transaction its processed-event row is written in,
+/// so the side effects and the duplicate check commit or roll back together.
+pub trait IdempotentHandler<T>: Send + Sync {
+    fn handle<'a>(
+        &'a self,
+        txn: &'a DatabaseTransaction,
+        metadata: &'a RecordMetadata,
+        payload: T,
+    ) -> BoxFuture<'a, Result<(), DbErr>>;
+
+    /// The id duplicates are detected by, the record's position by default. Override
+    /// it with an id carried by the payload to also skip events produced twice.
+    fn event_id(&self, metadata: &RecordMetadata, _payload: &T) -> String {
+        metadata.event_id()
+    }
+}
+
+impl KafkaConsumer {
+    /// Consumes the topic registered for `T`, skipping records the group already
+    /// processed according to the `processed_event` table. The Kafka offset is only
+    /// committed after the transaction, and a failing handler or schema lookup stops
+    /// consumption so the record is redelivered rather than lost. Records that can't
+    /// be decoded at all go to the `dead_letter_event` table instead.
+    pub async fn subscribe_idempotent<T: Topic, H: IdempotentHandler<T::Payload>>(
+        &self,
+        db: &DatabaseConnection,
+        handler: &H,
+    ) -> Result<(), IdempotentError> {
+        let topic = T::name();
+        let mut end_offsets = self.start(&topic).await?;
+        if end_offsets.as_ref().is_some_and(EndOffsets::is_done) {
+            info!("Nothing to read from {}", topic);
+            return Ok(());
+        }
+
+        while let Some(result) = self.next_message(&mut end_offsets).await {
+            let msg = result?;
+            let mut span = global::tracer("consumer")
+                .start_with_context("consume_payload_idempotent", &message_context(&msg));
+            let metadata = RecordMetadata::of(&msg);
+
+            let payload = match self.avro_decoder.decode(msg.payload()).await {
+                Ok(value) => from_value::<T::Payload>(&value.value).map_err(|e| e.to_string()),
+                Err(e) if is_undecodable(&e) => Err(e.to_string()),
+                Err(e) => {
+                    error!("Error decoding {}: {}", metadata.event_id(), e);
+                    span.end();
+                    return Err(e.into());
+                }
+            };
+            match payload {
+                Ok(payload) => {
+                    let event_id = handler.event_id(&metadata, &payload);
+                    let txn = db.begin().await?;
+                    if mark_processed(&txn, &self.group_id, &event_id).await? {
+                        if let Err(e) = handler.handle(&txn, &metadata, payload).await {
+                            error!("Error handling event {}: {}", event_id, e);
+                            txn.rollback().await?;
+                            span.end();
+                            return Err(e.into());
+                        }
+                        txn.commit().await?;
+                    } else {
+                        info!("Skipping already processed event {}", event_id);
+                        txn.rollback().await?;
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "Error deserializing {}, dead-lettering it: {}",
+                        metadata.event_id(),
+                        e
+                    );
+                    dead_letter(db, &self.group_id, &metadata.event_id(), &e, msg.payload())
+                        .await?;
+                }
+            }
+            self.consumer.commit_message(&msg, CommitMode::Async)?;
+            self.consumer
+                .context()
+                .mark_processed(msg.topic(), msg.partition(), msg.offset());
+            span.end();
+        }
+        info!("Read {} up to its end offsets", topic);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_malformed_records_are_undecodable() {
+        assert!(is_undecodable(&SRCError::non_retryable_without_cause(
+            "Invalid bytes [1, 0, 0, 0]"
+        )));
+        assert!(is_undecodable(&SRCError::non_retryable_with_cause(
+            "unexpected end of file",
+            "Could not transform bytes using schema"
+        )));
+        assert!(!is_undecodable(&SRCError::retryable_with_cause(
+            "connection refused",
+            "http call to schema registry failed"
+        )));
+        assert!(!is_undecodable(&SRCError::non_retryable_with_cause(
+            "expected value at line 1 column 1",
+            "could not parse to RawRegisteredSchema, schema might not exist on this schema registry, the http call failed, cause will give more information"
+        )));
+    }
+}
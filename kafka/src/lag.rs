@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, Instant},
+};
+
+use opentelemetry::{global, KeyValue};
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    error::KafkaResult,
+    Offset,
+};
+use serde::Serialize;
+use tracing::{error, warn};
+
+use crate::{consumer::KafkaConsumer, rebalance::RebalanceContext};
+
+const WATERMARK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub struct LagOptions {
+    /// How often lag is computed.
+    pub interval: Duration,
+    /// How long a lagging partition may go without its committed offset moving
+    /// before it is reported as stalled.
+    pub stall_after: Duration,
+}
+
+impl Default for LagOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            stall_after: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct PartitionLag {
+    pub topic: String,
+    pub partition: i32,
+    pub committed: Option<i64>,
+    pub high_watermark: i64,
+    /// High watermark minus the committed offset, or minus the low watermark
+    /// when nothing is committed yet.
+    pub lag: i64,
+    /// Seconds since the committed offset last moved.
+    pub idle_secs: u64,
+    pub stalled: bool,
+}
+
+struct Progress {
+    committed: Option<i64>,
+    since: Instant,
+}
+
+/// The latest lag of every partition assigned to a consumer, refreshed in the
+/// background for as long as the consumer lives.
+#[derive(Clone)]
+pub struct LagMonitor {
+    group_id: String,
+    lags: Arc<RwLock<Vec<PartitionLag>>>,
+}
+
+impl LagMonitor {
+    pub fn group_id(&self) -> &str {
+        &self.group_id
+    }
+
+    pub fn snapshot(&self) -> Vec<PartitionLag> {
+        self.lags.read().unwrap().clone()
+    }
+
+    pub fn total_lag(&self) -> i64 {
+        self.lags.read().unwrap().iter().map(|l| l.lag).sum()
+    }
+}
+
+impl KafkaConsumer {
+    /// Starts computing the lag of the assigned partitions every `options.interval`,
+    /// exported as the `kafka.consumer.lag` gauge and through the returned monitor.
+    pub fn monitor_lag(&self, options: LagOptions) -> LagMonitor {
+        let monitor = LagMonitor {
+            group_id: self.group_id.clone(),
+            lags: Arc::new(RwLock::new(Vec::new())),
+        };
+        register_gauge(&monitor);
+
+        let consumer = Arc::downgrade(&self.consumer);
+        let lags = monitor.lags.clone();
+        let group_id = self.group_id.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut progress = HashMap::new();
+            while let Some(result) = compute_lag(&consumer, &mut progress, &options) {
+                update_snapshot(&lags, result, &group_id);
+                std::thread::sleep(options.interval);
+            }
+        });
+        monitor
+    }
+}
+
+fn register_gauge(monitor: &LagMonitor) {
+    let meter = global::meter("consumer");
+    let gauge = meter
+        .i64_observable_gauge("kafka.consumer.lag")
+        .with_description("Messages between the committed offset and the high watermark")
+        .init();
+    let monitor = monitor.clone();
+    let registration = meter.register_callback(move |cx| {
+        for lag in monitor.lags.read().unwrap().iter() {
+            gauge.observe(
+                cx,
+                lag.lag,
+                &[
+                    KeyValue::new("group", monitor.group_id.clone()),
+                    KeyValue::new("topic", lag.topic.clone()),
+                    KeyValue::new("partition", lag.partition as i64),
+                ],
+            );
+        }
+    });
+    if let Err(e) = registration {
+        error!("Error registering lag gauge: {}", e);
+    }
+}
+
+/// One round of lag computation, or `None` once the consumer is gone.
+fn compute_lag(
+    consumer: &Weak<StreamConsumer<RebalanceContext>>,
+    progress: &mut HashMap<(String, i32), Progress>,
+    options: &LagOptions,
+) -> Option<KafkaResult<Vec<PartitionLag>>> {
+    let consumer = consumer.upgrade()?;
+    Some(partition_lags(&consumer, progress, options))
+}
+
+/// Replaces the snapshot with a new round of lags. A failed round keeps the last
+/// good snapshot, so that a transient error doesn't wipe the exported lag.
+fn update_snapshot(
+    lags: &RwLock<Vec<PartitionLag>>,
+    result: KafkaResult<Vec<PartitionLag>>,
+    group_id: &str,
+) {
+    match result {
+        Ok(snapshot) => {
+            for lag in snapshot.iter().filter(|l| l.stalled) {
+                warn!(
+                    "Consumer group {} stalled on {} partition {}: lag {} with no progress for {}s",
+                    group_id, lag.topic, lag.partition, lag.lag, lag.idle_secs
+                );
+            }
+            *lags.write().unwrap() = snapshot;
+        }
+        Err(e) => error!(
+            "Error computing lag of {}, keeping the last snapshot: {}",
+            group_id, e
+        ),
+    }
+}
+
+fn partition_lags(
+    consumer: &StreamConsumer<RebalanceContext>,
+    progress: &mut HashMap<(String, i32), Progress>,
+    options: &LagOptions,
+) -> KafkaResult<Vec<PartitionLag>> {
+    let assignment = consumer.assignment()?;
+    let committed = consumer.committed_offsets(assignment, WATERMARK_TIMEOUT)?;
+    let now = Instant::now();
+    let mut lags = Vec::new();
+    for elem in committed.elements() {
+        let (low, high) =
+            consumer.fetch_watermarks(elem.topic(), elem.partition(), WATERMARK_TIMEOUT)?;
+        let committed = match elem.offset() {
+            Offset::Offset(offset) => Some(offset),
+            _ => None,
+        };
+        lags.push(partition_lag(
+            elem.topic(),
+            elem.partition(),
+            committed,
+            (low, high),
+            progress,
+            now,
+            options,
+        ));
+    }
+    progress.retain(|(topic, partition), _| {
+        lags.iter()
+            .any(|l| &l.topic == topic && l.partition == *partition)
+    });
+    Ok(lags)
+}
+
+/// The lag of one partition given its watermarks, tracking in `progress` since
+/// when its committed offset hasn't moved.
+fn partition_lag(
+    topic: &str,
+    partition: i32,
+    committed: Option<i64>,
+    (low, high): (i64, i64),
+    progress: &mut HashMap<(String, i32), Progress>,
+    now: Instant,
+    options: &LagOptions,
+) -> PartitionLag {
+    let lag = (high - committed.unwrap_or(low)).max(0);
+    let entry = progress
+        .entry((topic.to_owned(), partition))
+        .or_insert(Progress {
+            committed,
+            since: now,
+        });
+    if entry.committed != committed || lag == 0 {
+        entry.committed = committed;
+        entry.since = now;
+    }
+    let idle = now.duration_since(entry.since);
+    PartitionLag {
+        topic: topic.to_owned(),
+        partition,
+        committed,
+        high_watermark: high,
+        lag,
+        idle_secs: idle.as_secs(),
+        stalled: lag > 0 && idle >= options.stall_after,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rdkafka::error::KafkaError;
+
+    use super::*;
+
+    fn options() -> LagOptions {
+        LagOptions {
+            interval: Duration::from_secs(1),
+            stall_after: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_partition_lag_stalls_without_progress() {
+        let mut progress = HashMap::new();
+        let start = Instant::now();
+        let lag = partition_lag("t", 0, Some(5), (0, 10), &mut progress, start, &options());
+        assert_eq!((5, false), (lag.lag, lag.stalled));
+
+        let later = start + Duration::from_secs(61);
+        let lag = partition_lag("t", 0, Some(5), (0, 12), &mut progress, later, &options());
+        assert_eq!((7, 61, true), (lag.lag, lag.idle_secs, lag.stalled));
+
+        let lag = partition_lag("t", 0, Some(8), (0, 12), &mut progress, later, &options());
+        assert_eq!((4, 0, false), (lag.lag, lag.idle_secs, lag.stalled));
+    }
+
+    #[test]
+    fn test_partition_lag_from_low_watermark() {
+        let mut progress = HashMap::new();
+        let lag = partition_lag(
+            "t",
+            1,
+            None,
+            (3, 10),
+            &mut progress,
+            Instant::now(),
+            &options(),
+        );
+        assert_eq!(7, lag.lag);
+    }
+
+    #[test]
+    fn test_update_snapshot_keeps_last_good_snapshot() {
+        let mut progress = HashMap::new();
+        let lag = partition_lag(
+            "t",
+            0,
+            Some(5),
+            (0, 10),
+            &mut progress,
+            Instant::now(),
+            &options(),
+        );
+        let lags = RwLock::new(Vec::new());
+        update_snapshot(&lags, Ok(vec![lag.clone()]), "g");
+        update_snapshot(&lags, Err(KafkaError::NoMessageReceived), "g");
+        assert_eq!(vec![lag], *lags.read().unwrap());
+    }
+}
@@ -1,11 +1,17 @@
+pub mod admin;
 pub mod avro;
+pub mod compatibility;
 pub mod consumer;
+pub mod idempotent;
+pub mod lag;
+pub mod multi_consumer;
+pub mod offsets;
 pub mod producer;
+pub mod rebalance;
+pub mod schema_registry;
 pub mod utils;
 
 pub mod commons {
-    use std::env;
-
     use schema_registry_converter::async_impl::schema_registry::SrSettings;
 
     pub fn create_schema_registry_settings(schema_registry_url: String) -> SrSettings {
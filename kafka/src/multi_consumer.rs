@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use apache_avro::{from_value, types::Value, AvroSchema};
+use common::events::topics::Topic;
+use dotenv::dotenv;
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    error::KafkaError,
+    Message,
+};
+use schema_registry_converter::async_impl::easy_avro::EasyAvroDecoder;
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{error, info, warn};
+
+use crate::{
+    avro::record_name,
+    commons::create_schema_registry_settings,
+    consumer::{consume_records, consumer_config, Change},
+};
+
+#[derive(Error, Debug)]
+pub enum MultiConsumerError {
+    #[error("Nothing to subscribe to, register a topic handler or a pattern")]
+    NoSubscription,
+
+    #[error("Kafka error")]
+    KafkaError(#[from] KafkaError),
+}
+
+/// Decodes an Avro value into one payload type and hands it on.
+trait RecordHandler: Send + Sync {
+    fn handle(&self, value: &Value) -> Result<(), String>;
+}
+
+struct ChannelHandler<T> {
+    sender: UnboundedSender<T>,
+}
+
+impl<T: for<'a> Deserialize<'a> + Send + Sync> RecordHandler for ChannelHandler<T> {
+    fn handle(&self, value: &Value) -> Result<(), String> {
+        let payload = from_value::<T>(value).map_err(|e| e.to_string())?;
+        self.sender.send(payload).map_err(|e| e.to_string())
+    }
+}
+
+/// A single group member reading several topics, dispatching each record to the
+/// handler registered for its Avro record name, or else for its topic.
+pub struct MultiTopicConsumer {
+    consumer: StreamConsumer,
+    avro_decoder: EasyAvroDecoder,
+    pattern: Option<String>,
+    topic_handlers: HashMap<String, Box<dyn RecordHandler>>,
+    record_handlers: HashMap<String, Box<dyn RecordHandler>>,
+}
+
+impl MultiTopicConsumer {
+    pub fn new(bootstrap_servers: String, group_id: String, schema_registry_url: String) -> Self {
+        dotenv().ok();
+        let consumer: StreamConsumer = consumer_config(bootstrap_servers, group_id)
+            .create()
+            .expect("Consumer creation error");
+        let avro_decoder =
+            EasyAvroDecoder::new(create_schema_registry_settings(schema_registry_url));
+        Self {
+            consumer,
+            avro_decoder,
+            pattern: None,
+            topic_handlers: HashMap::new(),
+            record_handlers: HashMap::new(),
+        }
+    }
+
+    /// Subscribes to the topic registered for `T` and sends its decoded payloads to `sender`.
+    pub fn on_topic<T: Topic>(mut self, sender: UnboundedSender<T::Payload>) -> Self
+    where
+        T::Payload: 'static,
+    {
+        self.topic_handlers
+            .insert(T::name(), Box::new(ChannelHandler { sender }));
+        self
+    }
+
+    /// Sends records whose Avro record name is that of `T` to `sender`, whichever
+    /// subscribed topic they are read from. Takes precedence over topic handlers.
+    pub fn on_record<T: AvroSchema + for<'a> Deserialize<'a> + Send + Sync + 'static>(
+        mut self,
+        sender: UnboundedSender<T>,
+    ) -> Self {
+        self.record_handlers.insert(
+            record_name(&T::get_schema()),
+            Box::new(ChannelHandler { sender }),
+        );
+        self
+    }
+
+    /// Also subscribes to every topic matching `pattern`, a regex such as `^books\..*`.
+    pub fn with_pattern(mut self, pattern: String) -> Self {
+        let pattern = if pattern.starts_with('^') {
+            pattern
+        } else {
+            format!("^{}", pattern)
+        };
+        self.pattern = Some(pattern);
+        self
+    }
+
+    /// The handler of a record read from `topic` with the Avro record name `record`.
+    fn handler(&self, topic: &str, record: Option<&str>) -> Option<&dyn RecordHandler> {
+        record
+            .and_then(|record| self.record_handlers.get(record))
+            .or_else(|| self.topic_handlers.get(topic))
+            .map(Box::as_ref)
+    }
+
+    /// Consumes until the consumer fails. Fails up front when there is nothing to
+    /// subscribe to, which needs at least one topic handler or a pattern.
+    pub async fn consume(&self) -> Result<(), MultiConsumerError> {
+        let mut subscription: Vec<&str> = self.topic_handlers.keys().map(String::as_str).collect();
+        if let Some(pattern) = &self.pattern {
+            subscription.push(pattern.as_str());
+        }
+        if subscription.is_empty() {
+            return Err(MultiConsumerError::NoSubscription);
+        }
+        self.consumer.subscribe(&subscription)?;
+        info!("Subscribed to {:?}", subscription);
+
+        consume_records(
+            &self.consumer,
+            &self.avro_decoder,
+            None,
+            false,
+            |msg, change| {
+                let decoded = match change {
+                    Change::Upsert(decoded) => decoded,
+                    Change::Tombstone(key) => {
+                        warn!(
+                            "Skipping tombstone for key '{}' from {} offset {}",
+                            key,
+                            msg.topic(),
+                            msg.offset()
+                        );
+                        return;
+                    }
+                };
+                let record = decoded.name.map(|name| name.fullname(None));
+                match self.handler(msg.topic(), record.as_deref()) {
+                    Some(handler) => {
+                        if let Err(e) = handler.handle(&decoded.value) {
+                            error!(
+                                "Error handling record from {} partition {} offset {}: {}",
+                                msg.topic(),
+                                msg.partition(),
+                                msg.offset(),
+                                e
+                            );
+                        }
+                    }
+                    None => warn!(
+                        "No handler for topic {} or record {:?}, skipping offset {}",
+                        msg.topic(),
+                        record,
+                        msg.offset()
+                    ),
+                }
+            },
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apache_avro::types::Value;
+    use common::events::{
+        dto::AnalyticsAlert as AnalyticsAlertPayload,
+        topics::{AnalyticsAlert, BookCreated},
+    };
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn consumer() -> MultiTopicConsumer {
+        MultiTopicConsumer::new(
+            "localhost:9092".to_owned(),
+            "multi-consumer-test".to_owned(),
+            "http://localhost:8081".to_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_consume_without_subscription_fails() {
+        let (sender, _) = unbounded_channel::<AnalyticsAlertPayload>();
+        let consumer = consumer().on_record(sender);
+        assert!(matches!(
+            consumer.consume().await,
+            Err(MultiConsumerError::NoSubscription)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_record_handlers_apply_to_every_topic() {
+        let (topic_sender, _) = unbounded_channel();
+        let (record_sender, mut records) = unbounded_channel::<AnalyticsAlertPayload>();
+        let consumer = consumer()
+            .on_topic::<BookCreated>(topic_sender)
+            .on_record(record_sender);
+        let record = record_name(&AnalyticsAlertPayload::get_schema());
+
+        let handler = consumer
+            .handler(&BookCreated::name(), Some(&record))
+            .expect("record handler");
+        // Not an alert, so the record handler is the one that rejects it.
+        assert!(handler.handle(&Value::Null).is_err());
+        assert!(records.try_recv().is_err());
+
+        assert!(consumer.handler(&BookCreated::name(), None).is_some());
+        assert!(consumer
+            .handler(&AnalyticsAlert::name(), Some(&record))
+            .is_some());
+        assert!(consumer.handler(&AnalyticsAlert::name(), None).is_none());
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use rdkafka::{
+    config::RDKafkaLogLevel,
+    consumer::{BaseConsumer, CommitMode, Consumer, ConsumerContext},
+    error::KafkaResult,
+    ClientConfig, Offset, TopicPartitionList,
+};
+
+/// A position in a partition, resolved to a concrete offset before use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OffsetSpec {
+    Earliest,
+    Latest,
+    /// The first offset whose timestamp is at or after these epoch milliseconds.
+    Timestamp(i64),
+    Offset(i64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartitionOffsets {
+    pub partition: i32,
+    pub committed: Option<i64>,
+    pub low_watermark: i64,
+    pub high_watermark: i64,
+}
+
+impl PartitionOffsets {
+    pub fn lag(&self) -> i64 {
+        self.high_watermark - self.committed.unwrap_or(self.low_watermark)
+    }
+}
+
+/// Creates a consumer in `group_id` that never subscribes, for offset lookups and commits.
+pub fn offsets_consumer(bootstrap_servers: &str, group_id: &str) -> KafkaResult<BaseConsumer> {
+    ClientConfig::new()
+        .set("group.id", group_id)
+        .set("bootstrap.servers", bootstrap_servers)
+        .set("enable.auto.commit", "false")
+        .set_log_level(RDKafkaLogLevel::Warning)
+        .create()
+}
+
+pub fn partitions<X: ConsumerContext, C: Consumer<X>>(
+    consumer: &C,
+    topic: &str,
+    timeout: Duration,
+) -> KafkaResult<Vec<i32>> {
+    let metadata = consumer.fetch_metadata(Some(topic), timeout)?;
+    Ok(metadata
+        .topics()
+        .iter()
+        .filter(|t| t.name() == topic)
+        .flat_map(|t| t.partitions().iter().map(|p| p.id()))
+        .collect())
+}
+
+/// Resolves `spec` to a concrete offset for each of `partitions`.
+pub fn resolve_offsets<X: ConsumerContext, C: Consumer<X>>(
+    consumer: &C,
+    topic: &str,
+    partitions: &[i32],
+    spec: OffsetSpec,
+    timeout: Duration,
+) -> KafkaResult<TopicPartitionList> {
+    let mut tpl = TopicPartitionList::new();
+    match spec {
+        OffsetSpec::Timestamp(timestamp) => {
+            let mut query = TopicPartitionList::new();
+            for partition in partitions {
+                query.add_partition_offset(topic, *partition, Offset::Offset(timestamp))?;
+            }
+            for elem in consumer.offsets_for_times(query, timeout)?.elements() {
+                let offset = match elem.offset() {
+                    Offset::Offset(offset) => offset,
+                    // No record at or after the timestamp.
+                    _ => {
+                        consumer
+                            .fetch_watermarks(topic, elem.partition(), timeout)?
+                            .1
+                    }
+                };
+                tpl.add_partition_offset(topic, elem.partition(), Offset::Offset(offset))?;
+            }
+        }
+        spec => {
+            for partition in partitions {
+                let (low, high) = consumer.fetch_watermarks(topic, *partition, timeout)?;
+                let offset = match spec {
+                    OffsetSpec::Earliest => low,
+                    OffsetSpec::Latest => high,
+                    OffsetSpec::Offset(offset) => offset.clamp(low, high),
+                    OffsetSpec::Timestamp(_) => unreachable!(),
+                };
+                tpl.add_partition_offset(topic, *partition, Offset::Offset(offset))?;
+            }
+        }
+    }
+    Ok(tpl)
+}
+
+/// Committed offsets and watermarks of `group_id` on every partition of `topic`.
+pub fn group_offsets(
+    bootstrap_servers: &str,
+    group_id: &str,
+    topic: &str,
+    timeout: Duration,
+) -> KafkaResult<Vec<PartitionOffsets>> {
+    let consumer = offsets_consumer(bootstrap_servers, group_id)?;
+    let mut query = TopicPartitionList::new();
+    for partition in partitions(&consumer, topic, timeout)? {
+        query.add_partition(topic, partition);
+    }
+    let committed = consumer.committed_offsets(query, timeout)?;
+    committed
+        .elements()
+        .iter()
+        .map(|elem| {
+            let (low_watermark, high_watermark) =
+                consumer.fetch_watermarks(topic, elem.partition(), timeout)?;
+            Ok(PartitionOffsets {
+                partition: elem.partition(),
+                committed: match elem.offset() {
+                    Offset::Offset(offset) => Some(offset),
+                    _ => None,
+                },
+                low_watermark,
+                high_watermark,
+            })
+        })
+        .collect()
+}
+
+/// Moves the committed offsets of `group_id` on `topic` to `spec`. The group must
+/// have no active members, or the broker rejects the commit.
+pub fn reset_group_offsets(
+    bootstrap_servers: &str,
+    group_id: &str,
+    topic: &str,
+    spec: OffsetSpec,
+    dry_run: bool,
+    timeout: Duration,
+) -> KafkaResult<Vec<(i32, i64)>> {
+    let consumer = offsets_consumer(bootstrap_servers, group_id)?;
+    let partitions = partitions(&consumer, topic, timeout)?;
+    let tpl = resolve_offsets(&consumer, topic, &partitions, spec, timeout)?;
+    if !dry_run {
+        consumer.commit(&tpl, CommitMode::Sync)?;
+    }
+    Ok(tpl
+        .elements()
+        .iter()
+        .filter_map(|elem| match elem.offset() {
+            Offset::Offset(offset) => Some((elem.partition(), offset)),
+            _ => None,
+        })
+        .collect())
+}
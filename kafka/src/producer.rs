@@ -1,7 +1,12 @@
-use std::{env, ops::Sub, sync::Arc};
+use std::sync::Arc;
 
-use crate::{commons::create_schema_registry_settings, utils};
+use crate::{
+    avro::{MessageKey, SubjectNaming, SubjectStrategies},
+    commons::create_schema_registry_settings,
+    utils,
+};
 use apache_avro::AvroSchema;
+use common::events::topics::{KeyFormat, Topic};
 use dotenv::dotenv;
 use opentelemetry::{
     global,
@@ -9,19 +14,11 @@ use opentelemetry::{
     Context, Key, KeyValue, StringValue,
 };
 use rdkafka::{
-    message::{Header, OwnedHeaders},
+    message::OwnedHeaders,
     producer::{FutureProducer, FutureRecord},
     ClientConfig,
 };
-use reqwest::{header, Client};
-use schema_registry_converter::{
-    async_impl::{
-        easy_avro::EasyAvroEncoder,
-        schema_registry::{SrSettings, SrSettingsBuilder},
-    },
-    avro_common::get_supplied_schema,
-    schema_registry_common::SubjectNameStrategy,
-};
+use schema_registry_converter::async_impl::easy_avro::EasyAvroEncoder;
 use serde::Serialize;
 use std::time::Duration;
 use tracing::{error, info};
@@ -59,13 +56,83 @@ impl KafkaProducer {
             producer,
         }
     }
-    pub async fn produce<T: Serialize + AvroSchema>(
+    pub async fn publish<T: Topic>(&self, key: T::Key, payload: T::Payload) -> bool {
+        let key = match T::KEY_FORMAT {
+            KeyFormat::Plain => MessageKey::Plain(key.to_string()),
+            KeyFormat::Avro => MessageKey::Avro(key),
+        };
+        self.produce(key, payload, T::name(), T::SUBJECT_STRATEGIES)
+            .await
+    }
+
+    /// Publishes a record without a value for `key`, so that compaction drops the key.
+    /// Only plain keys are supported, which is checked when `T` is known at compile time.
+    pub async fn publish_tombstone<T: Topic>(&self, key: T::Key) -> bool {
+        const {
+            assert!(
+                matches!(T::KEY_FORMAT, KeyFormat::Plain),
+                "Tombstones need a plain key"
+            )
+        };
+        let topic = T::name();
+        let key_bytes = key.to_string().into_bytes();
+        let record: FutureRecord<Vec<u8>, Vec<u8>> = FutureRecord::to(&topic)
+            .key(&key_bytes)
+            .headers(Self::trace_headers(&topic));
+        self.deliver(record).await
+    }
+
+    fn trace_headers(topic: &str) -> OwnedHeaders {
+        let mut span = global::tracer("producer").start("produce_to_kafka");
+        span.set_attribute(KeyValue {
+            key: Key::new(topic.to_owned()),
+            value: opentelemetry::Value::String(StringValue::from(topic.to_owned())),
+        });
+        let context = Context::current_with_span(span);
+        let mut headers = OwnedHeaders::new();
+
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut utils::HeaderInjector(&mut headers))
+        });
+        headers
+    }
+
+    async fn deliver(&self, record: FutureRecord<'_, Vec<u8>, Vec<u8>>) -> bool {
+        match self.producer.send(record, Duration::from_secs(60)).await {
+            Ok(_) => {
+                info!("message delivered");
+                true
+            }
+            Err((e, _)) => {
+                error!("{}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn produce<K: Serialize + AvroSchema, T: Serialize + AvroSchema>(
         &self,
-        key: String,
+        key: MessageKey<K>,
         msg: T,
         topic: String,
+        strategies: SubjectStrategies,
     ) -> bool {
-        let value_strategy = SubjectNameStrategy::TopicNameStrategy(topic.clone(), false);
+        let key_bytes = match key {
+            MessageKey::Plain(key) => key.into_bytes(),
+            MessageKey::Avro(key) => {
+                let key_strategy = strategies.key.name_strategy::<K>(&topic, true);
+                match self
+                    .avro_encoder
+                    .clone()
+                    .encode_struct(key, &key_strategy)
+                    .await
+                {
+                    Ok(v) => v,
+                    Err(e) => panic!("Error getting key: {}", e),
+                }
+            }
+        };
+        let value_strategy = strategies.value.name_strategy::<T>(&topic, false);
         let payload = match self
             .avro_encoder
             .clone()
@@ -76,32 +143,10 @@ impl KafkaProducer {
             Err(e) => panic!("Error getting payload: {}", e),
         };
 
-        let mut span = global::tracer("producer").start("produce_to_kafka");
-        span.set_attribute(KeyValue {
-            key: Key::new(topic.clone()),
-            value: opentelemetry::Value::String(StringValue::from(topic.clone())),
-        });
-        let context = Context::current_with_span(span);
-        let mut headers = OwnedHeaders::new().insert(Header {
-            key: &key,
-            value: Some("value"),
-        });
-
-        global::get_text_map_propagator(|propagator| {
-            propagator.inject_context(&context, &mut utils::HeaderInjector(&mut headers))
-        });
         let record = FutureRecord::to(&topic)
             .payload(&payload)
-            .key(&key)
-            .headers(headers);
-
-        let delivery_status = self.producer.send(record, Duration::from_secs(60)).await;
-        if delivery_status.is_err() {
-            error!("{}", delivery_status.err().unwrap().0.to_string());
-            return false;
-        } else {
-            info!("message delivered");
-            return true;
-        }
+            .key(&key_bytes)
+            .headers(Self::trace_headers(&topic));
+        self.deliver(record).await
     }
 }
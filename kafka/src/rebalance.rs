@@ -0,0 +1,316 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock, Weak,
+    },
+};
+
+use futures::future::BoxFuture;
+use opentelemetry::{
+    global,
+    trace::{Span, Tracer},
+    KeyValue,
+};
+use rdkafka::{
+    client::NativeClient,
+    consumer::{
+        CommitMode, Consumer, ConsumerContext, Rebalance, RebalanceProtocol, StreamConsumer,
+    },
+    error::{KafkaError, KafkaResult},
+    types::RDKafkaRespErr,
+    ClientContext, Offset, TopicPartitionList,
+};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tracing::{error, info};
+
+/// Async hooks run while the consumer is rebalancing. Consumption is paused
+/// until they return, so they should only flush or load per-partition state.
+///
+/// Rebalances are handled synchronously inside the consumer's poll, so the hooks are
+/// driven with `block_in_place`, which requires a multi-threaded Tokio runtime. On a
+/// current-thread runtime they are skipped and an error is logged.
+pub trait RebalanceListener: Send + Sync {
+    fn on_assign(&self, partitions: Vec<(String, i32)>) -> BoxFuture<'_, ()>;
+
+    /// Runs before the offsets of the revoked partitions are committed.
+    fn on_revoke(&self, partitions: Vec<(String, i32)>) -> BoxFuture<'_, ()>;
+}
+
+#[derive(Clone, Default)]
+pub struct RebalanceOptions {
+    /// Needs a multi-threaded Tokio runtime, see `RebalanceListener`.
+    pub listener: Option<Arc<dyn RebalanceListener>>,
+    /// Uses `partition.assignment.strategy=cooperative-sticky`, so a rebalance
+    /// only revokes the partitions that actually move.
+    pub cooperative_sticky: bool,
+}
+
+/// Consumer context that logs and traces rebalances, runs the listener hooks,
+/// starts newly assigned partitions from their start offsets and commits the
+/// offsets processed on revoked partitions before giving them up.
+pub struct RebalanceContext {
+    listener: Option<Arc<dyn RebalanceListener>>,
+    consumer: OnceLock<Weak<StreamConsumer<RebalanceContext>>>,
+    commit_on_revoke: AtomicBool,
+    /// The next offset to read after the last record handled, by topic and partition.
+    processed: Mutex<HashMap<(String, i32), i64>>,
+    /// Where partitions start the first time they are assigned, instead of the
+    /// group's committed offsets.
+    start_offsets: Mutex<HashMap<(String, i32), Offset>>,
+}
+
+impl RebalanceContext {
+    pub fn new(listener: Option<Arc<dyn RebalanceListener>>) -> Self {
+        Self {
+            listener,
+            consumer: OnceLock::new(),
+            commit_on_revoke: AtomicBool::new(true),
+            processed: Mutex::new(HashMap::new()),
+            start_offsets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts the partitions of `offsets` from their offset when the group first
+    /// assigns them. Partitions without a concrete or logical offset are left out.
+    pub fn set_start_offsets(&self, offsets: &TopicPartitionList) {
+        let mut start_offsets = self.start_offsets.lock().unwrap();
+        for elem in offsets.elements() {
+            if !matches!(elem.offset(), Offset::Stored | Offset::Invalid) {
+                start_offsets.insert((elem.topic().to_owned(), elem.partition()), elem.offset());
+            }
+        }
+    }
+
+    /// Records that the record at `offset` was handled, so it is committed on revoke.
+    /// The consumer's position can be ahead of it, with records fetched but not handled.
+    pub fn mark_processed(&self, topic: &str, partition: i32, offset: i64) {
+        self.processed
+            .lock()
+            .unwrap()
+            .insert((topic.to_owned(), partition), offset + 1);
+    }
+
+    pub fn set_commit_on_revoke(&self, commit_on_revoke: bool) {
+        self.commit_on_revoke
+            .store(commit_on_revoke, Ordering::Relaxed);
+    }
+
+    /// Gives the context the consumer it belongs to, which it needs to commit on revoke.
+    pub fn attach(&self, consumer: &Arc<StreamConsumer<RebalanceContext>>) {
+        let _ = self.consumer.set(Arc::downgrade(consumer));
+    }
+
+    fn commit_processed(&self, revoked: &TopicPartitionList) {
+        let to_commit = take_processed(&mut self.processed.lock().unwrap(), revoked);
+        if to_commit.count() == 0 {
+            return;
+        }
+        let Some(consumer) = self.consumer.get().and_then(Weak::upgrade) else {
+            return;
+        };
+        match consumer.commit(&to_commit, CommitMode::Sync) {
+            Ok(()) => info!("Committed {:?} before revoke", to_commit),
+            Err(e) => error!("Error committing offsets before revoke: {}", e),
+        }
+    }
+
+    /// Assigns or unassigns `tpl` as rdkafka's default rebalance does.
+    fn apply(&self, rebalance: &Rebalance<'_>, tpl: &TopicPartitionList) -> KafkaResult<()> {
+        let Some(consumer) = self.consumer.get().and_then(Weak::upgrade) else {
+            error!("Rebalance before the consumer was attached");
+            return Ok(());
+        };
+        let cooperative = matches!(
+            consumer.rebalance_protocol(),
+            RebalanceProtocol::Cooperative
+        );
+        match rebalance {
+            Rebalance::Assign(_) if cooperative => consumer.incremental_assign(tpl),
+            Rebalance::Assign(_) => consumer.assign(tpl),
+            _ if cooperative => consumer.incremental_unassign(tpl),
+            _ => consumer.unassign(),
+        }
+    }
+
+    fn run_hook(&self, hook: impl FnOnce(&dyn RebalanceListener) -> BoxFuture<'_, ()>) {
+        let Some(listener) = self.listener.as_deref() else {
+            return;
+        };
+        match Handle::try_current() {
+            Ok(handle) if matches!(handle.runtime_flavor(), RuntimeFlavor::MultiThread) => {
+                tokio::task::block_in_place(|| handle.block_on(hook(listener)))
+            }
+            _ => error!("Rebalance hooks need a multi-threaded Tokio runtime, skipping"),
+        }
+    }
+}
+
+/// Sets the start offset of the partitions of `assigned` that have one, removing it
+/// so a partition assigned again later resumes from the group's committed offset.
+fn take_start_offsets(
+    start_offsets: &mut HashMap<(String, i32), Offset>,
+    assigned: &mut TopicPartitionList,
+) {
+    let partitions: Vec<(String, i32)> = partitions(assigned);
+    for (topic, partition) in partitions {
+        if let Some(offset) = start_offsets.remove(&(topic.clone(), partition)) {
+            if let Err(e) = assigned.set_partition_offset(&topic, partition, offset) {
+                error!(
+                    "Error starting {} partition {} at {:?}: {}",
+                    topic, partition, offset, e
+                );
+            }
+        }
+    }
+}
+
+/// Removes the processed offsets of the `revoked` partitions, returning them to commit.
+fn take_processed(
+    processed: &mut HashMap<(String, i32), i64>,
+    revoked: &TopicPartitionList,
+) -> TopicPartitionList {
+    let mut to_commit = TopicPartitionList::new();
+    for elem in revoked.elements() {
+        if let Some(offset) = processed.remove(&(elem.topic().to_owned(), elem.partition())) {
+            let _ = to_commit.add_partition_offset(
+                elem.topic(),
+                elem.partition(),
+                Offset::Offset(offset),
+            );
+        }
+    }
+    to_commit
+}
+
+fn partitions(tpl: &TopicPartitionList) -> Vec<(String, i32)> {
+    tpl.elements()
+        .iter()
+        .map(|elem| (elem.topic().to_owned(), elem.partition()))
+        .collect()
+}
+
+impl ClientContext for RebalanceContext {}
+
+impl ConsumerContext for RebalanceContext {
+    /// The default rebalance, except that assigned partitions start from their
+    /// start offsets, which is only possible while the assignment is applied.
+    fn rebalance(
+        &self,
+        _native_client: &NativeClient,
+        err: RDKafkaRespErr,
+        tpl: &mut TopicPartitionList,
+    ) {
+        if err == RDKafkaRespErr::RD_KAFKA_RESP_ERR__ASSIGN_PARTITIONS {
+            take_start_offsets(&mut self.start_offsets.lock().unwrap(), tpl);
+        }
+        let rebalance = match err {
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__ASSIGN_PARTITIONS => Rebalance::Assign(tpl),
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS => Rebalance::Revoke(tpl),
+            _ => Rebalance::Error(KafkaError::Rebalance(err.into())),
+        };
+        self.pre_rebalance(&rebalance);
+        if let Err(e) = self.apply(&rebalance, tpl) {
+            error!("Error applying rebalance: {}", e);
+        }
+        self.post_rebalance(&rebalance);
+    }
+
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        if let Rebalance::Revoke(tpl) = rebalance {
+            let revoked = partitions(tpl);
+            info!("Partitions revoked: {:?}", revoked);
+            let mut span = global::tracer("consumer").start("rebalance_revoke");
+            span.set_attribute(KeyValue::new("partitions", format!("{:?}", revoked)));
+            self.run_hook(|listener| listener.on_revoke(revoked));
+            if self.commit_on_revoke.load(Ordering::Relaxed) {
+                self.commit_processed(tpl);
+            }
+            span.end();
+        }
+    }
+
+    fn post_rebalance(&self, rebalance: &Rebalance<'_>) {
+        match rebalance {
+            Rebalance::Assign(tpl) => {
+                let assigned = partitions(tpl);
+                info!("Partitions assigned: {:?}", assigned);
+                let mut span = global::tracer("consumer").start("rebalance_assign");
+                span.set_attribute(KeyValue::new("partitions", format!("{:?}", assigned)));
+                self.run_hook(|listener| listener.on_assign(assigned));
+                span.end();
+            }
+            Rebalance::Revoke(_) => {}
+            Rebalance::Error(e) => error!("Rebalance error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_offsets_only_apply_to_the_first_assignment() {
+        let context = RebalanceContext::new(None);
+        let mut offsets = TopicPartitionList::new();
+        offsets
+            .add_partition_offset("books", 0, Offset::Offset(42))
+            .unwrap();
+        offsets
+            .add_partition_offset("books", 1, Offset::Stored)
+            .unwrap();
+        offsets
+            .add_partition_offset("books", 2, Offset::Beginning)
+            .unwrap();
+        context.set_start_offsets(&offsets);
+
+        let mut assigned = TopicPartitionList::new();
+        assigned.add_partition("books", 0);
+        assigned.add_partition("books", 1);
+        take_start_offsets(&mut context.start_offsets.lock().unwrap(), &mut assigned);
+        assert_eq!(
+            Offset::Offset(42),
+            assigned.find_partition("books", 0).unwrap().offset()
+        );
+        assert_eq!(
+            Offset::Invalid,
+            assigned.find_partition("books", 1).unwrap().offset()
+        );
+
+        let mut reassigned = TopicPartitionList::new();
+        reassigned.add_partition("books", 0);
+        reassigned.add_partition("books", 2);
+        take_start_offsets(&mut context.start_offsets.lock().unwrap(), &mut reassigned);
+        assert_eq!(
+            Offset::Invalid,
+            reassigned.find_partition("books", 0).unwrap().offset()
+        );
+        assert_eq!(
+            Offset::Beginning,
+            reassigned.find_partition("books", 2).unwrap().offset()
+        );
+    }
+
+    #[test]
+    fn test_take_processed_only_takes_revoked_partitions() {
+        let context = RebalanceContext::new(None);
+        context.mark_processed("books", 0, 4);
+        context.mark_processed("books", 0, 7);
+        context.mark_processed("books", 1, 2);
+        let mut revoked = TopicPartitionList::new();
+        revoked.add_partition("books", 0);
+        revoked.add_partition("books", 2);
+
+        let to_commit = take_processed(&mut context.processed.lock().unwrap(), &revoked);
+        assert_eq!(1, to_commit.count());
+        assert_eq!(
+            Offset::Offset(8),
+            to_commit.find_partition("books", 0).unwrap().offset()
+        );
+        assert_eq!(
+            HashMap::from([(("books".to_owned(), 1), 3)]),
+            *context.processed.lock().unwrap()
+        );
+    }
+}
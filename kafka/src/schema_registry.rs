@@ -0,0 +1,109 @@
+use apache_avro::Schema;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+
+const SCHEMA_REGISTRY_CONTENT_TYPE: &str = "application/vnd.schemaregistry.v1+json";
+
+#[derive(Error, Debug)]
+pub enum SchemaRegistryError {
+    #[error("Schema registry request error")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("Schema registry response error")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Schema registry returned {status}: {body}")]
+    RegistryError { status: StatusCode, body: String },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SubjectVersion {
+    pub subject: String,
+    pub id: u32,
+    pub version: u32,
+    pub schema: String,
+}
+
+#[derive(Deserialize)]
+struct RegisteredId {
+    id: u32,
+}
+
+/// A thin client for the Schema Registry REST endpoints the encoders don't cover.
+pub struct SchemaRegistryClient {
+    schema_registry_url: String,
+    client: Client,
+}
+
+impl SchemaRegistryClient {
+    pub fn new(schema_registry_url: String) -> Self {
+        Self {
+            schema_registry_url: schema_registry_url.trim_end_matches('/').to_owned(),
+            client: Client::new(),
+        }
+    }
+
+    pub async fn subjects(&self) -> Result<Vec<String>, SchemaRegistryError> {
+        let body = self
+            .get(&format!("{}/subjects", self.schema_registry_url))
+            .await?
+            .unwrap_or_else(|| "[]".to_owned());
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// The latest version of `subject`, or `None` when nothing is registered under it.
+    pub async fn latest(
+        &self,
+        subject: &str,
+    ) -> Result<Option<SubjectVersion>, SchemaRegistryError> {
+        match self
+            .get(&format!(
+                "{}/subjects/{}/versions/latest",
+                self.schema_registry_url, subject
+            ))
+            .await?
+        {
+            Some(body) => Ok(Some(serde_json::from_str(&body)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Registers `schema` under `subject` and returns its id. The schema goes in full,
+    /// defaults included, which its Parsing Canonical Form would drop.
+    pub async fn register(
+        &self,
+        subject: &str,
+        schema: &Schema,
+    ) -> Result<u32, SchemaRegistryError> {
+        let response = self
+            .client
+            .post(format!(
+                "{}/subjects/{}/versions",
+                self.schema_registry_url, subject
+            ))
+            .header(reqwest::header::CONTENT_TYPE, SCHEMA_REGISTRY_CONTENT_TYPE)
+            .body(json!({ "schema": serde_json::to_string(schema)? }).to_string())
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if status.is_success() {
+            Ok(serde_json::from_str::<RegisteredId>(&body)?.id)
+        } else {
+            Err(SchemaRegistryError::RegistryError { status, body })
+        }
+    }
+
+    async fn get(&self, url: &str) -> Result<Option<String>, SchemaRegistryError> {
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        match status {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(body)),
+            status => Err(SchemaRegistryError::RegistryError { status, body }),
+        }
+    }
+}
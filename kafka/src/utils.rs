@@ -1,4 +1,6 @@
-use apache_avro::Schema;
+use crate::avro::{SubjectNaming, SubjectStrategy};
+use apache_avro::{AvroSchema, Schema};
+use common::events::topics::{KeyFormat, Topic};
 use opentelemetry::propagation::{Extractor, Injector};
 use rdkafka::message::{BorrowedHeaders, Headers, OwnedHeaders};
 use schema_registry_converter::{
@@ -17,12 +19,9 @@ impl<'a> Injector for HeaderInjector<'a> {
             value: Some(&value),
         });
 
-        for header in self.0.iter() {
-            let s = String::from_utf8(header.value.unwrap().to_vec()).unwrap();
-            new = new.insert(rdkafka::message::Header {
-                key,
-                value: Some(&value),
-            });
+        // Keeps the other headers, replacing any earlier value of `key`.
+        for header in self.0.iter().filter(|header| header.key != key) {
+            new = new.insert(header);
         }
         self.0.clone_from(&new);
     }
@@ -47,12 +46,93 @@ impl<'a> Extractor for HeaderExtractor<'a> {
     }
 }
 
-pub async fn register_schema(
+/// Registers the schema of `T` under the subject that `strategy` derives for `topic`.
+pub async fn register_schema<T: AvroSchema>(
     schema_registry_url: String,
-    subject: String,
-    schema: Schema,
+    topic: &str,
+    strategy: SubjectStrategy,
+    is_key: bool,
 ) -> Result<RegisteredSchema, SRCError> {
     let sr_settings = SrSettings::new(schema_registry_url);
-    let supplied_schema: SuppliedSchema = *get_supplied_schema(&schema);
-    post_schema(&sr_settings, subject, supplied_schema).await
+    let subject = strategy.subject::<T>(topic, is_key)?;
+    post_schema(&sr_settings, subject, supplied_schema(&T::get_schema())?).await
+}
+
+/// `schema` as supplied to the registry: in full, since its Parsing Canonical Form,
+/// which `get_supplied_schema` supplies, drops the defaults of added fields.
+fn supplied_schema(schema: &Schema) -> Result<SuppliedSchema, SRCError> {
+    let schema_json = serde_json::to_string(schema)
+        .map_err(|e| SRCError::non_retryable_with_cause(e, "Could not serialize schema"))?;
+    Ok(SuppliedSchema {
+        schema: schema_json,
+        ..*get_supplied_schema(schema)
+    })
+}
+
+/// Registers the value schema of `T`, and its key schema when keys are Avro-encoded.
+pub async fn register_topic_schemas<T: Topic>(
+    schema_registry_url: String,
+) -> Result<Vec<RegisteredSchema>, SRCError> {
+    let topic = T::name();
+    let mut registered = vec![
+        register_schema::<T::Payload>(
+            schema_registry_url.clone(),
+            &topic,
+            T::SUBJECT_STRATEGIES.value,
+            false,
+        )
+        .await?,
+    ];
+    if T::KEY_FORMAT == KeyFormat::Avro {
+        registered.push(
+            register_schema::<T::Key>(schema_registry_url, &topic, T::SUBJECT_STRATEGIES.key, true)
+                .await?,
+        );
+    }
+    Ok(registered)
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::propagation::Injector;
+    use rdkafka::message::{Header, Headers, OwnedHeaders};
+
+    use apache_avro::Schema;
+
+    use super::{supplied_schema, HeaderInjector};
+
+    #[test]
+    fn test_header_injector_replaces_its_key() {
+        let mut headers = OwnedHeaders::new().insert(Header {
+            key: "other",
+            value: Some("kept"),
+        });
+        HeaderInjector(&mut headers).set("traceparent", "first".to_owned());
+        HeaderInjector(&mut headers).set("traceparent", "second".to_owned());
+        let headers: Vec<(&str, Option<&[u8]>)> = headers
+            .iter()
+            .map(|header| (header.key, header.value))
+            .collect();
+        assert_eq!(
+            vec![
+                ("traceparent", Some("second".as_bytes())),
+                ("other", Some("kept".as_bytes())),
+            ],
+            headers
+        );
+    }
+
+    #[test]
+    fn test_supplied_schema_keeps_defaults() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "Book", "namespace": "books", "fields": [
+                {"name": "language", "type": ["null", "string"], "default": null}
+            ]}"#,
+        )
+        .unwrap();
+        let supplied = supplied_schema(&schema).unwrap();
+        assert_eq!(Some("books.Book".to_owned()), supplied.name);
+        assert_eq!(schema, Schema::parse_str(&supplied.schema).unwrap());
+        assert!(supplied.schema.contains(r#""default":null"#));
+    }
 }
@@ -0,0 +1,62 @@
+pub mod offsets;
+pub mod produce;
+pub mod schemas;
+pub mod tail;
+pub mod topics;
+
+use std::str::FromStr;
+
+use common::events::constants::Topics;
+use kafka::avro::name_strategy;
+use schema_registry_converter::schema_registry_common::get_subject;
+use strum::IntoEnumIterator;
+
+/// The typed topic named `topic`, or every typed topic when `None`.
+pub fn typed_topics(topic: Option<&str>) -> Result<Vec<Topics>, Box<dyn std::error::Error>> {
+    match topic {
+        Some(topic) => {
+            Ok(vec![Topics::from_str(topic).map_err(|_| {
+                format!("{} is not a registered topic", topic)
+            })?])
+        }
+        None => Ok(Topics::iter().collect()),
+    }
+}
+
+/// The value subject of `topic`, named by its strategy when it is a typed topic
+/// and `<topic>-value` otherwise.
+pub fn value_subject(topic: &str) -> Result<String, Box<dyn std::error::Error>> {
+    match Topics::from_str(topic) {
+        Ok(typed) => Ok(get_subject(&name_strategy(
+            typed.subject_strategies().value,
+            topic,
+            &typed.payload_schema(),
+            false,
+        ))?),
+        Err(_) => Ok(format!("{}-value", topic)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_subject() {
+        assert_eq!(
+            format!("{}-value", Topics::BookCreated),
+            value_subject(&Topics::BookCreated.to_string()).unwrap()
+        );
+        assert_eq!("untyped-value", value_subject("untyped").unwrap());
+    }
+
+    #[test]
+    fn test_typed_topics() {
+        assert_eq!(Topics::iter().count(), typed_topics(None).unwrap().len());
+        assert_eq!(
+            vec![Topics::AnalyticsAlert],
+            typed_topics(Some(&Topics::AnalyticsAlert.to_string())).unwrap()
+        );
+        assert!(typed_topics(Some("untyped")).is_err());
+    }
+}
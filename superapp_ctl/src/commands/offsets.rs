@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use kafka::offsets::{group_offsets, reset_group_offsets};
+
+use crate::{Cli, OffsetsCommand};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn run(cli: &Cli, command: &OffsetsCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        OffsetsCommand::Show { group, topic } => {
+            println!("partition\tcommitted\tlow\thigh\tlag");
+            for offsets in group_offsets(&cli.bootstrap_servers, group, topic, TIMEOUT)? {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    offsets.partition,
+                    offsets
+                        .committed
+                        .map(|o| o.to_string())
+                        .unwrap_or_else(|| "-".to_owned()),
+                    offsets.low_watermark,
+                    offsets.high_watermark,
+                    offsets.lag()
+                );
+            }
+        }
+        OffsetsCommand::Reset {
+            group,
+            topic,
+            target,
+            dry_run,
+        } => {
+            let reset = reset_group_offsets(
+                &cli.bootstrap_servers,
+                group,
+                topic,
+                target.spec(),
+                *dry_run,
+                TIMEOUT,
+            )?;
+            for (partition, offset) in reset {
+                println!(
+                    "{}{} partition {} -> {}",
+                    if *dry_run { "[dry-run] " } else { "" },
+                    topic,
+                    partition,
+                    offset
+                );
+            }
+        }
+    }
+    Ok(())
+}
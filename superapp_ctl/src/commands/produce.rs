@@ -0,0 +1,60 @@
+use std::{
+    io::{self, Read},
+    time::Duration,
+};
+
+use apache_avro::Schema;
+use kafka::{avro::encode_json, producer::KafkaProducer, schema_registry::SchemaRegistryClient};
+use rdkafka::producer::FutureRecord;
+
+use super::value_subject;
+use crate::Cli;
+
+pub async fn run(
+    cli: &Cli,
+    topic: &str,
+    key: Option<String>,
+    value: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let value = match value {
+        Some(value) => value,
+        None => {
+            let mut value = String::new();
+            io::stdin().read_to_string(&mut value)?;
+            value
+        }
+    };
+    let json: serde_json::Value = serde_json::from_str(&value)?;
+
+    let subject = value_subject(topic)?;
+    let registered = SchemaRegistryClient::new(cli.schema_registry_url.clone())
+        .latest(&subject)
+        .await?
+        .ok_or_else(|| format!("No schema registered under {}", subject))?;
+    let schema = Schema::parse_str(&registered.schema)?;
+    let payload = encode_json(registered.id, &schema, json).map_err(|e| {
+        format!(
+            "Value does not match {} version {}: {}",
+            subject, registered.version, e
+        )
+    })?;
+
+    let producer = KafkaProducer::new(
+        cli.bootstrap_servers.clone(),
+        cli.schema_registry_url.clone(),
+    );
+    let mut record = FutureRecord::<String, Vec<u8>>::to(topic).payload(&payload);
+    if let Some(key) = &key {
+        record = record.key(key);
+    }
+    let (partition, offset) = producer
+        .producer
+        .send(record, Duration::from_secs(60))
+        .await
+        .map_err(|(e, _)| e)?;
+    println!(
+        "Produced to {} partition {} offset {}",
+        topic, partition, offset
+    );
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use apache_avro::Schema;
+use common::events::compatibility::{check_compatibility, CompatibilityLevel};
+use kafka::schema_registry::SchemaRegistryClient;
+
+use super::{typed_topics, value_subject};
+use crate::{Cli, SchemasCommand};
+
+pub async fn run(cli: &Cli, command: &SchemasCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let registry = SchemaRegistryClient::new(cli.schema_registry_url.clone());
+    match command {
+        SchemasCommand::List => {
+            for subject in registry.subjects().await? {
+                if let Some(latest) = registry.latest(&subject).await? {
+                    println!("{}\tversion {}\tid {}", subject, latest.version, latest.id);
+                }
+            }
+        }
+        SchemasCommand::Register { topic } => {
+            for topic in typed_topics(topic.as_deref())? {
+                let subject = value_subject(&topic.to_string())?;
+                let id = registry.register(&subject, &topic.payload_schema()).await?;
+                println!("{}\tid {}", subject, id);
+            }
+        }
+        SchemasCommand::Diff { topic } => {
+            for topic in typed_topics(topic.as_deref())? {
+                let subject = value_subject(&topic.to_string())?;
+                let local = topic.payload_schema();
+                let Some(registered) = registry.latest(&subject).await? else {
+                    println!("{}: not registered", subject);
+                    continue;
+                };
+                let registered_schema = Schema::parse_str(&registered.schema)?;
+                if registered_schema.canonical_form() == local.canonical_form() {
+                    println!("{}: identical to version {}", subject, registered.version);
+                    continue;
+                }
+                println!("{}: differs from version {}", subject, registered.version);
+                println!(
+                    "--- registered\n{}",
+                    serde_json::to_string_pretty(&registered_schema)?
+                );
+                println!("+++ local\n{}", serde_json::to_string_pretty(&local)?);
+                match check_compatibility(&local, &registered_schema, CompatibilityLevel::Full) {
+                    Ok(()) => println!("fully compatible"),
+                    Err(errors) => errors.iter().for_each(|e| println!("  {}", e)),
+                }
+            }
+        }
+    }
+    Ok(())
+}
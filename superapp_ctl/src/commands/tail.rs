@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use kafka::{
+    avro::{schema_id, to_json},
+    commons::create_schema_registry_settings,
+    offsets::{partitions, resolve_offsets, OffsetSpec},
+};
+use rdkafka::{
+    config::RDKafkaLogLevel,
+    consumer::{Consumer, StreamConsumer},
+    ClientConfig, Message,
+};
+use schema_registry_converter::async_impl::easy_avro::EasyAvroDecoder;
+use serde_json::{json, Value};
+
+use crate::Cli;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn run(
+    cli: &Cli,
+    topic: &str,
+    from_beginning: bool,
+    limit: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Partitions are assigned directly, so tailing never joins or disturbs a group.
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", "superapp-ctl-tail")
+        .set("bootstrap.servers", &cli.bootstrap_servers)
+        .set("enable.auto.commit", "false")
+        .set_log_level(RDKafkaLogLevel::Warning)
+        .create()?;
+    let spec = if from_beginning {
+        OffsetSpec::Earliest
+    } else {
+        OffsetSpec::Latest
+    };
+    let partitions = partitions(&consumer, topic, TIMEOUT)?;
+    consumer.assign(&resolve_offsets(
+        &consumer,
+        topic,
+        &partitions,
+        spec,
+        TIMEOUT,
+    )?)?;
+
+    let decoder = EasyAvroDecoder::new(create_schema_registry_settings(
+        cli.schema_registry_url.clone(),
+    ));
+    let mut printed = 0;
+    while limit.is_none_or(|limit| printed < limit) {
+        let msg = consumer.recv().await?;
+        let key = match msg.key() {
+            Some(key) if schema_id(key).is_some() => decode(&decoder, key).await,
+            Some(key) => raw(key),
+            None => json!(null),
+        };
+        let value = match msg.payload() {
+            Some(payload) => decode(&decoder, payload).await,
+            None => json!(null),
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "partition": msg.partition(),
+                "offset": msg.offset(),
+                "timestamp": msg.timestamp().to_millis(),
+                "key": key,
+                "value": value,
+            }))?
+        );
+        printed += 1;
+    }
+    Ok(())
+}
+
+/// `bytes` decoded as Avro, or printed raw when they aren't Avro the registry knows,
+/// so one foreign record doesn't end the tail.
+async fn decode(decoder: &EasyAvroDecoder, bytes: &[u8]) -> Value {
+    match decoder.decode(Some(bytes)).await {
+        Ok(decoded) => to_json(decoded.value).unwrap_or_else(|_| raw(bytes)),
+        Err(_) => raw(bytes),
+    }
+}
+
+fn raw(bytes: &[u8]) -> Value {
+    json!(String::from_utf8_lossy(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_non_avro_payloads_are_printed_raw() {
+        // Nothing listens there, so wire-format payloads can't be decoded either.
+        let decoder = EasyAvroDecoder::new(create_schema_registry_settings(
+            "http://127.0.0.1:1".to_owned(),
+        ));
+        assert_eq!(json!("plain text"), decode(&decoder, b"plain text").await);
+        assert_eq!(
+            json!("\0\0\0\0\u{1}x"),
+            decode(&decoder, &[0, 0, 0, 0, 1, b'x']).await
+        );
+    }
+}
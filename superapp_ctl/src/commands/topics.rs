@@ -0,0 +1,42 @@
+use common::events::topics::definitions;
+use kafka::admin::KafkaAdmin;
+
+use crate::{Cli, TopicsCommand};
+
+pub async fn run(cli: &Cli, command: &TopicsCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let admin = KafkaAdmin::new(cli.bootstrap_servers.clone());
+    match command {
+        TopicsCommand::List => {
+            let mut topics = admin.describe_topics(None).await?;
+            topics.sort_by(|a, b| a.name.cmp(&b.name));
+            for topic in topics.iter().filter(|t| !t.name.starts_with("__")) {
+                println!("{}\t{} partitions", topic.name, topic.partitions.len());
+            }
+        }
+        TopicsCommand::Describe { topic } => {
+            for info in admin.describe_topics(Some(topic)).await? {
+                println!("Topic: {}", info.name);
+                for partition in &info.partitions {
+                    println!(
+                        "  partition {}\tleader {}\treplicas {:?}\tisr {:?}",
+                        partition.id, partition.leader, partition.replicas, partition.isr
+                    );
+                }
+                println!("Configs:");
+                for (name, value) in admin.topic_configs(&info.name).await? {
+                    println!("  {}={}", name, value);
+                }
+            }
+        }
+        TopicsCommand::Provision { dry_run } => {
+            let changes = admin.provision(&definitions(), *dry_run).await?;
+            if changes.is_empty() {
+                println!("All topics are up to date");
+            }
+            for change in changes {
+                println!("{}{}", if *dry_run { "[dry-run] " } else { "" }, change);
+            }
+        }
+    }
+    Ok(())
+}
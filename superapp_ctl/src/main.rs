@@ -0,0 +1,150 @@
+pub mod commands;
+
+use clap::{Args, Parser, Subcommand};
+use kafka::offsets::OffsetSpec;
+
+/// Operator tooling for the superapp Kafka topics, schemas and consumer groups.
+#[derive(Parser)]
+#[command(name = "superapp-ctl")]
+pub struct Cli {
+    #[arg(
+        long,
+        env = "KAFKA_BOOTSTRAP_SERVERS",
+        default_value = "localhost:9092"
+    )]
+    pub bootstrap_servers: String,
+
+    #[arg(
+        long,
+        env = "SCHEMA_REGISTRY_URL",
+        default_value = "http://localhost:8081"
+    )]
+    pub schema_registry_url: String,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand)]
+    Topics(TopicsCommand),
+    #[command(subcommand)]
+    Schemas(SchemasCommand),
+    #[command(subcommand)]
+    Offsets(OffsetsCommand),
+    /// Print records of a topic as pretty JSON, decoding Avro through the Schema Registry
+    /// and printing anything else raw.
+    Tail {
+        topic: String,
+        /// Start from the earliest offset instead of only new records.
+        #[arg(long)]
+        from_beginning: bool,
+        /// Stop after this many records.
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Produce a JSON value to a topic, validated against its registered value schema.
+    Produce {
+        topic: String,
+        #[arg(long)]
+        key: Option<String>,
+        /// The value as JSON; read from stdin when omitted.
+        #[arg(long)]
+        value: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TopicsCommand {
+    List,
+    Describe {
+        topic: String,
+    },
+    /// Create or update topics from their definitions in `common::events::topics`.
+    Provision {
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SchemasCommand {
+    /// List subjects with their latest version and id.
+    List,
+    /// Register the value schema of one or every typed topic.
+    Register {
+        #[arg(long)]
+        topic: Option<String>,
+    },
+    /// Compare the local value schema of one or every typed topic with the registered one.
+    Diff {
+        #[arg(long)]
+        topic: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum OffsetsCommand {
+    Show {
+        #[arg(long)]
+        group: String,
+        #[arg(long)]
+        topic: String,
+    },
+    /// Move the committed offsets of a group with no active members.
+    Reset {
+        #[arg(long)]
+        group: String,
+        #[arg(long)]
+        topic: String,
+        #[command(flatten)]
+        target: ResetTarget,
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct ResetTarget {
+    #[arg(long)]
+    pub earliest: bool,
+    #[arg(long)]
+    pub latest: bool,
+    /// Epoch milliseconds.
+    #[arg(long)]
+    pub timestamp: Option<i64>,
+    #[arg(long)]
+    pub offset: Option<i64>,
+}
+
+impl ResetTarget {
+    pub fn spec(&self) -> OffsetSpec {
+        match (self.earliest, self.latest, self.timestamp, self.offset) {
+            (true, ..) => OffsetSpec::Earliest,
+            (_, true, ..) => OffsetSpec::Latest,
+            (_, _, Some(timestamp), _) => OffsetSpec::Timestamp(timestamp),
+            (_, _, _, Some(offset)) => OffsetSpec::Offset(offset),
+            _ => unreachable!("clap requires one reset target"),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    match &cli.command {
+        Command::Topics(command) => commands::topics::run(&cli, command).await,
+        Command::Schemas(command) => commands::schemas::run(&cli, command).await,
+        Command::Offsets(command) => commands::offsets::run(&cli, command),
+        Command::Tail {
+            topic,
+            from_beginning,
+            limit,
+        } => commands::tail::run(&cli, topic, *from_beginning, *limit).await,
+        Command::Produce { topic, key, value } => {
+            commands::produce::run(&cli, topic, key.clone(), value.clone()).await
+        }
+    }
+}